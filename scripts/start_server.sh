@@ -8,9 +8,11 @@ REGION=$(cat /tmp/deploy/ECR_REGION)
 APPLICATION_ID=$(aws ssm get-parameter --region $REGION --name /bananagrabber/application_id --with-decryption | jq -r '.Parameter.Value')
 GUILD_ID=$(aws ssm get-parameter --region $REGION --name /bananagrabber/guild_id --with-decryption | jq -r '.Parameter.Value')
 DISCORD_TOKEN=$(aws ssm get-parameter --region $REGION --name /bananagrabber/discord_token --with-decryption | jq -r '.Parameter.Value')
+IMGUR_CLIENT_ID=$(aws ssm get-parameter --region $REGION --name /bananagrabber/imgur_client_id --with-decryption | jq -r '.Parameter.Value // empty')
 
 docker run \
   -e APPLICATION_ID=$APPLICATION_ID \
   -e DISCORD_TOKEN=$DISCORD_TOKEN \
   -e GUILD_ID=$GUILD_ID \
+  -e IMGUR_CLIENT_ID=$IMGUR_CLIENT_ID \
   --rm -d "$(cat /tmp/deploy/ECR_REGISTRY)/$(cat /tmp/deploy/target)"
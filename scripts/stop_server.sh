@@ -6,5 +6,6 @@ then
     exit 0 # nothing to stop
 fi
 
-docker kill $(docker ps -q)
+# SIGTERM, giving the bot time to finish what it is doing before it is killed
+docker stop --time 30 $(docker ps -q)
 docker image prune -af
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 
 pub fn get_args() -> CliOpts {
     CliOpts::parse()
@@ -9,6 +9,16 @@ pub fn get_args() -> CliOpts {
 pub struct CliOpts {
     #[clap(short, long, parse(from_occurrences))]
     pub verbose: u8,
+    /// keep extraction results in this sqlite database
+    #[cfg(feature = "sqlite-cache")]
+    #[clap(long, global = true)]
+    pub cache_db: Option<String>,
+    /// record every http request and response to this cassette file
+    #[clap(long, global = true, conflicts_with = "replay")]
+    pub record: Option<String>,
+    /// answer http requests from a recorded cassette instead of the network
+    #[clap(long, global = true)]
+    pub replay: Option<String>,
     #[clap(subcommand)]
     pub subcmd: SubCommand,
 }
@@ -16,8 +26,13 @@ pub struct CliOpts {
 #[derive(Parser, Debug)]
 pub enum SubCommand {
     ExtractMediaUrl(ExtractMediaUrl),
+    ExtractBatch(ExtractBatch),
+    Download(Download),
     FetchTestCase(FetchTestCase),
+    #[cfg(feature = "discord")]
     Bot(BotSettings),
+    #[cfg(feature = "http-server")]
+    Serve(ServeSettings),
     Test(Test),
 }
 
@@ -25,18 +40,147 @@ pub enum SubCommand {
 pub struct ExtractMediaUrl {
     /// url to the reddit post
     pub url: String,
+    #[clap(long, arg_enum, default_value = "text")]
+    pub format: OutputFormat,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// a json record per link, see the README for the schema
+    Json,
+}
+
+/// Extract every reddit link in a file, printing a line per link
+#[derive(Parser, Debug)]
+pub struct ExtractBatch {
+    /// file with a url per line, or chat log lines containing links; `-` for stdin
+    #[clap(default_value = "-")]
+    pub input: String,
+    /// how many links to extract at once
+    #[clap(long, default_value = "4")]
+    pub concurrency: usize,
+    #[clap(long, arg_enum, default_value = "text")]
+    pub format: OutputFormat,
+}
+
+/// Save the media of a reddit link, with a json file describing the post
+#[derive(Parser, Debug)]
+pub struct Download {
+    /// url to the reddit post
+    pub url: String,
+    /// directory to save into
+    #[clap(short, long, default_value = ".")]
+    pub dir: String,
+    /// don't fetch the separate audio of v.redd.it videos
+    #[clap(long)]
+    pub no_audio: bool,
 }
 
 #[derive(Parser, Debug)]
 pub struct FetchTestCase {
     /// url to the reddit post
     pub url: String,
+    /// save the response as <dir>/<name>.json, instead of printing it
+    #[clap(long)]
+    pub name: Option<String>,
+    #[clap(long, default_value = "sample_responses")]
+    pub dir: String,
+    /// only keep the fields that are parsed, and drop the comments
+    #[clap(long)]
+    pub minimize: bool,
+    /// overwrite an existing fixture
+    #[clap(long)]
+    pub force: bool,
 }
 
+#[cfg(feature = "discord")]
 #[derive(Parser, Debug)]
-pub struct BotSettings {}
+pub struct BotSettings {
+    /// serve /healthz, /readyz and /metrics on this address
+    #[cfg(feature = "metrics")]
+    #[clap(long)]
+    pub health_addr: Option<std::net::SocketAddr>,
+    /// upload media as attachments when it fits the server's upload limit,
+    /// linking to it otherwise
+    #[clap(long)]
+    pub upload_media: bool,
+    /// with --upload-media, shrink videos that are too big with ffmpeg
+    #[cfg(feature = "ffmpeg")]
+    #[clap(long, requires = "upload-media")]
+    pub transcode: bool,
+    /// how many ffmpeg jobs may run at once
+    #[cfg(feature = "ffmpeg")]
+    #[clap(long, default_value = "2")]
+    pub transcode_jobs: usize,
+    /// seconds a video may take to shrink before it is linked instead
+    #[cfg(feature = "ffmpeg")]
+    #[clap(long, default_value = "120")]
+    pub transcode_timeout: u64,
+    /// seconds to let in-flight extractions finish after SIGTERM or SIGINT
+    #[clap(long, default_value = "20")]
+    pub shutdown_deadline: u64,
+    /// write the final metrics to this file on shutdown
+    #[cfg(feature = "metrics")]
+    #[clap(long)]
+    pub metrics_file: Option<std::path::PathBuf>,
+    /// which shards to run: `auto`, a total (`8`), or a range of a total (`0-3/8`)
+    #[clap(long, default_value = "single")]
+    pub shards: bananagrabber::bot::Sharding,
+    /// reply to every message with a reddit link in it
+    #[clap(long)]
+    pub auto_detect: bool,
+    /// keep track of automatic replies in this json file, across restarts
+    #[clap(long)]
+    pub reply_state: Option<std::path::PathBuf>,
+    /// hours that automatic replies follow edits and deletes of their message
+    #[clap(long, default_value = "24")]
+    pub reply_window: u64,
+    /// how often each user can ask for media, as requests/seconds, or off
+    #[clap(long, default_value = "5/60")]
+    pub user_quota: bananagrabber::bot::Quota,
+    /// how often media can be asked for in each channel
+    #[clap(long, default_value = "20/60")]
+    pub channel_quota: bananagrabber::bot::Quota,
+    /// how often media can be asked for in each server
+    #[clap(long, default_value = "60/60")]
+    pub guild_quota: bananagrabber::bot::Quota,
+    /// keep the settings servers make with /bananagrabber-settings in this json file
+    #[clap(long)]
+    pub guild_settings: Option<std::path::PathBuf>,
+    /// keep the subreddits channels follow with /bananagrabber-subscribe in this json file
+    #[clap(long)]
+    pub subscriptions: Option<std::path::PathBuf>,
+    /// seconds between checks of followed subreddits for new posts
+    #[clap(long, default_value = "300")]
+    pub poll_interval: u64,
+}
+
+/// Run the http api.
+///
+/// Api keys can also be given as a comma separated `BANANAGRABBER_API_KEYS`,
+/// and basic auth as `BANANAGRABBER_BASIC_AUTH`.
+#[cfg(feature = "http-server")]
+#[derive(Parser, Debug)]
+pub struct ServeSettings {
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    pub addr: std::net::SocketAddr,
+    /// seconds an extraction may take before the request fails
+    #[clap(long, default_value = "30")]
+    pub timeout: u64,
+    /// accept requests with this key, in `x-api-key` or `?key=`
+    #[clap(long)]
+    pub api_key: Vec<String>,
+    /// accept requests with basic auth, as `user:password`
+    #[clap(long)]
+    pub basic_auth: Option<String>,
+}
 
 #[derive(Parser, Debug)]
 pub struct Test {
+    /// directory of fixtures, e.g. sample_responses
     pub file: String,
+    /// rewrite the expected results to match what is extracted now
+    #[clap(long)]
+    pub update: bool,
 }
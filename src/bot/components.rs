@@ -0,0 +1,203 @@
+//! The buttons under the bot's replies.
+//!
+//! Everything a button needs is kept on the message itself, so they keep
+//! working across restarts: the link to the post is the "Show post" button's
+//! url, and who asked is in the "Delete" button's id.
+use crate::media::Media;
+use serenity::builder::CreateComponents;
+use serenity::model::channel::Message;
+use serenity::model::interactions::message_component::{ActionRowComponent, ButtonStyle};
+
+const PREFIX: &str = "bananagrabber";
+/// Discord refuses link buttons with longer urls
+const MAX_URL_LEN: usize = 512;
+
+/// What pressing a button asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    /// Show this item of the gallery
+    Page(usize),
+    /// Remove the reply, which the user who asked for it can always do
+    Delete { requester: u64 },
+}
+
+impl Action {
+    fn custom_id(&self) -> String {
+        match self {
+            Action::Page(page) => format!("{}:page:{}", PREFIX, page),
+            Action::Delete { requester } => format!("{}:delete:{}", PREFIX, requester),
+        }
+    }
+
+    pub(super) fn parse(custom_id: &str) -> Option<Action> {
+        let mut parts = custom_id.split(':');
+        if parts.next()? != PREFIX {
+            return None;
+        }
+        match (parts.next()?, parts.next()?.parse().ok()?) {
+            ("page", page) => Some(Action::Page(page as usize)),
+            ("delete", requester) => Some(Action::Delete { requester }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Buttons {
+    post_url: Option<String>,
+    requester: u64,
+    page: usize,
+    pages: usize,
+}
+
+impl Buttons {
+    pub(super) fn new(post_url: &str, requester: u64) -> Buttons {
+        // discord rejects the whole message over a link button it doesn't like
+        let post_url = url::Url::parse(post_url)
+            .ok()
+            .filter(|u| u.scheme() == "https" || u.scheme() == "http")
+            .map(|u| u.to_string())
+            .filter(|u| u.len() <= MAX_URL_LEN);
+        Buttons {
+            post_url,
+            requester,
+            page: 0,
+            pages: 1,
+        }
+    }
+
+    /// Add previous and next buttons, for a gallery
+    pub(super) fn paged(mut self, page: usize, pages: usize) -> Buttons {
+        self.page = page;
+        self.pages = pages;
+        self
+    }
+
+    /// The buttons on a reply the bot made earlier
+    pub(super) fn from_message(message: &Message) -> Option<Buttons> {
+        let mut post_url = None;
+        let mut requester = None;
+        let buttons = message.components.iter().flat_map(|row| &row.components);
+        for component in buttons {
+            if let ActionRowComponent::Button(b) = component {
+                if b.url.is_some() {
+                    post_url = b.url.clone();
+                }
+                if let Some(Action::Delete { requester: r }) =
+                    b.custom_id.as_deref().and_then(Action::parse)
+                {
+                    requester = Some(r);
+                }
+            }
+        }
+        Some(Buttons {
+            post_url,
+            requester: requester?,
+            page: 0,
+            pages: 1,
+        })
+    }
+
+    pub(super) fn post_url(&self) -> Option<&str> {
+        self.post_url.as_deref()
+    }
+
+    pub(super) fn build<'a>(&self, c: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.pages > 1 {
+            let (page, pages) = (self.page, self.pages);
+            c.create_action_row(|row| {
+                row.create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .label("◀")
+                        .custom_id(Action::Page(page.saturating_sub(1)).custom_id())
+                        .disabled(page == 0)
+                })
+                .create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .label(format!("{}/{}", page + 1, pages))
+                        // only here to show where we are
+                        .custom_id(format!("{}:position", PREFIX))
+                        .disabled(true)
+                })
+                .create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .label("▶")
+                        .custom_id(Action::Page(page + 1).custom_id())
+                        .disabled(page + 1 >= pages)
+                })
+            });
+        }
+        c.create_action_row(|row| {
+            if let Some(url) = &self.post_url {
+                row.create_button(|b| b.style(ButtonStyle::Link).label("Show post").url(url));
+            }
+            row.create_button(|b| {
+                b.style(ButtonStyle::Danger).label("Delete").custom_id(
+                    Action::Delete {
+                        requester: self.requester,
+                    }
+                    .custom_id(),
+                )
+            })
+        })
+    }
+}
+
+/// One item of a gallery, discord embeds the link
+pub(super) fn page_content(media: &[Media], page: usize) -> String {
+    media.get(page).map(|m| m.url.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_ids_round_trip() {
+        for action in [Action::Page(3), Action::Delete { requester: 1234 }] {
+            assert_eq!(Action::parse(&action.custom_id()), Some(action));
+        }
+        assert_eq!(Action::parse("bananagrabber:position"), None);
+        assert_eq!(Action::parse("something:page:1"), None);
+    }
+
+    #[test]
+    fn link_button_needs_a_web_url() {
+        let b = Buttons::new("https://redd.it/saj6w9", 1);
+        assert_eq!(b.post_url(), Some("https://redd.it/saj6w9"));
+        assert_eq!(Buttons::new("not a link", 1).post_url(), None);
+        assert_eq!(Buttons::new("javascript:alert(1)", 1).post_url(), None);
+        let long = format!("https://redd.it/saj6w9?{}", "a".repeat(512));
+        assert_eq!(Buttons::new(&long, 1).post_url(), None);
+    }
+
+    #[test]
+    fn read_back_from_message() {
+        let mut components = CreateComponents::default();
+        Buttons::new("https://redd.it/saj6w9", 42)
+            .paged(1, 3)
+            .build(&mut components);
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "author": {"id": "3", "username": "bananagrabber", "discriminator": "0001", "avatar": null},
+            "content": "",
+            "timestamp": "2022-02-01T00:00:00+00:00",
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+            "components": components.0,
+        }))
+        .unwrap();
+        assert_eq!(
+            Buttons::from_message(&message),
+            Some(Buttons::new("https://redd.it/saj6w9", 42))
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! A small http listener next to the bot, so whatever runs the container
+//! can tell if it is up.
+//!
+//! * `/healthz` is ok as long as the process is running
+//! * `/readyz` is ok once every shard is connected and the commands are registered
+//! * `/metrics` is in the prometheus text format
+use super::Readiness;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub async fn serve(addr: SocketAddr, readiness: Arc<Readiness>) -> anyhow::Result<()> {
+    let make_svc = make_service_fn(move |_conn| {
+        let readiness = readiness.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let readiness = readiness.clone();
+                async move { Ok::<_, Infallible>(handle(&readiness, req)) }
+            }))
+        }
+    });
+    log::info!("health listener on http://{}", addr);
+    hyper::Server::try_bind(&addr)?.serve(make_svc).await?;
+    Ok(())
+}
+
+fn handle(readiness: &Readiness, req: Request<Body>) -> Response<Body> {
+    match req.uri().path() {
+        "/healthz" => text(StatusCode::OK, "ok".to_string()),
+        "/readyz" => match readiness.not_ready_reason() {
+            None => text(StatusCode::OK, "ready".to_string()),
+            Some(reason) => text(StatusCode::SERVICE_UNAVAILABLE, reason.to_string()),
+        },
+        "/metrics" => Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(crate::metrics::render()))
+            .unwrap(),
+        _ => text(StatusCode::NOT_FOUND, "not found".to_string()),
+    }
+}
+
+fn text(status: StatusCode, msg: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(msg))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    fn status(readiness: &Readiness, path: &str) -> StatusCode {
+        handle(readiness, Request::get(path).body(Body::empty()).unwrap()).status()
+    }
+
+    #[test]
+    fn ready_once_connected_and_registered() {
+        let readiness = Readiness::default();
+        assert_eq!(status(&readiness, "/healthz"), StatusCode::OK);
+        assert_eq!(
+            status(&readiness, "/readyz"),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        readiness.shard_connected(0, true);
+        readiness.shard_connected(1, false);
+        assert_eq!(
+            status(&readiness, "/readyz"),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        readiness.shard_connected(1, true);
+        assert_eq!(
+            status(&readiness, "/readyz"),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        readiness.commands_registered.store(true, Ordering::SeqCst);
+        assert_eq!(status(&readiness, "/readyz"), StatusCode::OK);
+    }
+
+    #[test]
+    fn metrics_are_served() {
+        assert_eq!(status(&Readiness::default(), "/metrics"), StatusCode::OK);
+    }
+}
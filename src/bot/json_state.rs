@@ -0,0 +1,102 @@
+//! State the bot keeps in a json file, so it survives a restart.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The json file one of the bot's stores is kept in
+#[derive(Debug)]
+pub(super) struct StateFile {
+    path: PathBuf,
+    /// Held while the file is written, so two saves never interleave
+    saving: Arc<Mutex<()>>,
+}
+
+impl StateFile {
+    pub(super) fn new(path: PathBuf) -> StateFile {
+        StateFile {
+            path,
+            saving: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The state saved in the file, or the default when nothing has been
+    /// saved yet
+    pub(super) fn load<T: DeserializeOwned + Default>(&self) -> anyhow::Result<T> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(s) => Ok(serde_json::from_str(&s)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the state `snapshot` returns, off the async workers. The
+    /// snapshot is only taken once this save has the file to itself, so
+    /// whichever save runs last writes the newest state.
+    pub(super) async fn save<T, F>(&self, snapshot: F) -> anyhow::Result<()>
+    where
+        T: Serialize,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (path, saving) = (self.path.clone(), self.saving.clone());
+        tokio::task::spawn_blocking(move || {
+            let _saving = saving.lock().unwrap();
+            write(&path, &snapshot())
+        })
+        .await?
+    }
+}
+
+fn write<T: Serialize>(path: &Path, state: &T) -> anyhow::Result<()> {
+    // write then rename, so a crash never leaves half a file, and name the
+    // temporary file after this process so another one can't write it too
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, serde_json::to_string(state)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// A path in the temp directory that no other test uses
+#[cfg(test)]
+pub(super) fn test_path(name: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    std::env::temp_dir().join(format!(
+        "bananagrabber-{}-{}-{}.json",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn last_save_wins() {
+        let file = Arc::new(StateFile::new(test_path("state")));
+        let empty: HashMap<u64, u64> = file.load().unwrap();
+        assert!(empty.is_empty());
+
+        let state = Arc::new(Mutex::new(HashMap::new()));
+        let saves: Vec<_> = (0..20)
+            .map(|i| {
+                state.lock().unwrap().insert(i, i);
+                let (file, state) = (file.clone(), state.clone());
+                tokio::spawn(async move { file.save(move || state.lock().unwrap().clone()).await })
+            })
+            .collect();
+        for saved in saves {
+            saved.await.unwrap().unwrap();
+        }
+        let reloaded: HashMap<u64, u64> = file.load().unwrap();
+        std::fs::remove_file(file.path()).unwrap();
+        assert_eq!(reloaded.len(), 20);
+    }
+}
@@ -0,0 +1,1212 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommands,
+    client::bridge::gateway::event::ShardStageUpdateEvent,
+    gateway::ConnectionStage,
+    http::AttachmentType,
+    model::{
+        channel::{Message, Reaction, ReactionType},
+        event::MessageUpdateEvent,
+        gateway::Ready,
+        id::{ChannelId, MessageId, UserId},
+    },
+    model::{
+        id::GuildId,
+        interactions::{
+            application_command::{
+                ApplicationCommand, ApplicationCommandInteraction,
+                ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType,
+            },
+            message_component::MessageComponentInteraction,
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+    prelude::*,
+};
+
+use reqwest::Url;
+
+use crate::media::{Media, MediaKind};
+use crate::media_extraction::{Extractor, MediaOutcome};
+use crate::metrics;
+use crate::reddit::{reddit_links_in_text, ListingSort, Quote};
+use components::Buttons;
+use quota::{Cooldown, Scope, Throttle};
+use settings::SettingsStore;
+use subscriptions::{Subscription, SubscriptionStore};
+use tracker::{ReplyTracker, TrackedReply};
+
+mod components;
+#[cfg(feature = "metrics")]
+mod health;
+mod json_state;
+mod quota;
+mod settings;
+mod sharding;
+mod shutdown;
+mod subscriptions;
+mod tracker;
+#[cfg(feature = "ffmpeg")]
+mod transcode;
+mod upload;
+
+#[cfg(feature = "ffmpeg")]
+pub use transcode::TranscodeConfig;
+
+const MAX_EMBEDDED_MEDIA: usize = 5;
+/// Reacting with this to an automatic reply removes it
+const DELETE_EMOJI: &str = "\u{1f5d1}\u{fe0f}";
+/// Discord refuses messages longer than this
+const MAX_MESSAGE_LEN: usize = 2000;
+
+pub use quota::{Quota, Quotas};
+pub use sharding::Sharding;
+pub use shutdown::DEFAULT_DEADLINE;
+pub use subscriptions::DEFAULT_POLL_INTERVAL;
+pub use tracker::DEFAULT_WINDOW;
+
+/// How the bot is run, beyond the discord credentials in the environment
+#[derive(Debug)]
+pub struct BotConfig {
+    /// Serve `/healthz`, `/readyz` and `/metrics` here
+    #[cfg(feature = "metrics")]
+    pub health_addr: Option<std::net::SocketAddr>,
+    /// Upload media as attachments when it fits, instead of linking it
+    pub upload_media: bool,
+    /// Shrink videos that are too big to upload
+    #[cfg(feature = "ffmpeg")]
+    pub transcode: Option<TranscodeConfig>,
+    /// How long to wait for in-flight extractions when asked to stop
+    pub shutdown_deadline: Duration,
+    /// Write the final metrics here on shutdown
+    #[cfg(feature = "metrics")]
+    pub metrics_file: Option<std::path::PathBuf>,
+    pub sharding: Sharding,
+    /// Reply to any message with a reddit link, without waiting for `/bananagrabber`
+    pub auto_detect: bool,
+    /// Keep track of automatic replies in this json file, so they follow
+    /// their source message across restarts
+    pub reply_state: Option<std::path::PathBuf>,
+    /// How long automatic replies follow their source message
+    pub reply_window: Duration,
+    /// How often each user, channel and guild can ask for media, unless a
+    /// guild has changed its own
+    pub quotas: Quotas,
+    /// Keep the settings guilds make with `/bananagrabber-settings` in this json file
+    pub guild_settings: Option<std::path::PathBuf>,
+    /// Keep the subreddits channels follow, and what was posted, in this json file
+    pub subscriptions: Option<std::path::PathBuf>,
+    /// How often to check followed subreddits for new posts
+    pub poll_interval: Duration,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            #[cfg(feature = "metrics")]
+            health_addr: None,
+            upload_media: false,
+            #[cfg(feature = "ffmpeg")]
+            transcode: None,
+            shutdown_deadline: DEFAULT_DEADLINE,
+            #[cfg(feature = "metrics")]
+            metrics_file: None,
+            sharding: Sharding::default(),
+            auto_detect: false,
+            reply_state: None,
+            reply_window: DEFAULT_WINDOW,
+            quotas: Quotas::default(),
+            guild_settings: None,
+            subscriptions: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+/// Whether the bot is able to answer commands yet
+#[derive(Debug, Default)]
+struct Readiness {
+    /// Whether each shard we have heard from is connected
+    shards: Mutex<BTreeMap<u64, bool>>,
+    commands_registered: AtomicBool,
+}
+
+impl Readiness {
+    fn shard_connected(&self, shard: u64, connected: bool) {
+        self.shards.lock().unwrap().insert(shard, connected);
+        metrics::shard_connected(shard, connected);
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn not_ready_reason(&self) -> Option<String> {
+        let shards = self.shards.lock().unwrap();
+        if shards.is_empty() {
+            return Some("gateway is not connected".to_string());
+        }
+        let down: Vec<String> = shards
+            .iter()
+            .filter(|(_, &connected)| !connected)
+            .map(|(shard, _)| shard.to_string())
+            .collect();
+        if !down.is_empty() {
+            Some(format!("shards {} are not connected", down.join(", ")))
+        } else if !self.commands_registered.load(Ordering::SeqCst) {
+            Some("commands are not registered".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+struct Handler {
+    extractor: Arc<Extractor>,
+    readiness: Arc<Readiness>,
+    shutdown: Arc<shutdown::Shutdown>,
+    upload_media: bool,
+    auto_detect: bool,
+    tracker: ReplyTracker,
+    quotas: Quotas,
+    throttle: Throttle,
+    settings: SettingsStore,
+    subscriptions: Arc<SubscriptionStore>,
+    #[cfg(feature = "ffmpeg")]
+    transcoder: Option<transcode::Transcoder>,
+}
+
+impl Handler {
+    /// Count a request against the quotas of the user, channel and guild it came from
+    fn check_quota(
+        &self,
+        user: UserId,
+        channel: ChannelId,
+        guild: Option<GuildId>,
+    ) -> Result<(), Cooldown> {
+        let mut keys = vec![(Scope::User, user.0), (Scope::Channel, channel.0)];
+        let quotas = match guild {
+            Some(guild) => {
+                keys.push((Scope::Guild, guild.0));
+                self.settings.get(guild.0).quotas(&self.quotas)
+            }
+            None => self.quotas,
+        };
+        let checked = self.throttle.check(&quotas, &keys, Instant::now());
+        if let Err(cooldown) = &checked {
+            log::debug!("{} is over the {} quota", user, cooldown.scope.name());
+            metrics::rate_limited(&format!("{}_quota", cooldown.scope.name()));
+        }
+        checked
+    }
+
+    /// `/bananagrabber-settings`, for people who can manage the guild
+    async fn guild_settings(&self, command: &ApplicationCommandInteraction) -> String {
+        let guild = match command.guild_id {
+            Some(g) => g,
+            None => return "settings are only for servers".to_string(),
+        };
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|o| o.name == name)
+                .and_then(|o| o.value.as_ref())
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+        };
+        let scope = match option("scope").as_deref() {
+            Some("user") => Some(Scope::User),
+            Some("channel") => Some(Scope::Channel),
+            Some("guild") => Some(Scope::Guild),
+            _ => None,
+        };
+
+        if let (Some(scope), Some(quota)) = (scope, option("quota")) {
+            let manager = command
+                .member
+                .as_ref()
+                .and_then(|m| m.permissions)
+                .is_some_and(|p| p.manage_guild());
+            if !manager {
+                return "only people who can manage the server can change its settings".to_string();
+            }
+            let quota: Quota = match quota.parse() {
+                Ok(q) => q,
+                Err(e) => return format!("{}: {}", quota, e),
+            };
+            let limit = self.quotas.get(scope);
+            if !quota.within(limit) {
+                return format!(
+                    "the {} quota can't be looser than {}, the bot's own",
+                    scope.name(),
+                    limit
+                );
+            }
+            let updated = self
+                .settings
+                .update(guild.0, |s| {
+                    s.quotas.insert(scope, quota);
+                })
+                .await;
+            if let Err(e) = updated {
+                log::error!("could not save settings for {}: {}", guild, e);
+                return "could not save the settings, try again later".to_string();
+            }
+        }
+
+        let quotas = self.settings.get(guild.0).quotas(&self.quotas);
+        let lines: Vec<String> = Scope::ALL
+            .iter()
+            .map(|&s| format!("{} quota: {}", s.name(), quotas.get(s)))
+            .collect();
+        format!("{}\n(requests/seconds, or off)", lines.join("\n"))
+    }
+
+    /// `/bananagrabber-subscribe`, `-unsubscribe` and `-subscriptions`, which
+    /// only people who can manage the channel can change
+    async fn channel_subscriptions(&self, command: &ApplicationCommandInteraction) -> String {
+        let channel = command.channel_id.0;
+        let option = |name: &str| {
+            command
+                .data
+                .options
+                .iter()
+                .find(|o| o.name == name)
+                .and_then(|o| o.value.as_ref())
+        };
+        let subreddit = option("subreddit")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim_start_matches("r/").to_string());
+
+        if command.data.name != "bananagrabber-subscriptions" {
+            let manager = command
+                .member
+                .as_ref()
+                .and_then(|m| m.permissions)
+                .is_some_and(|p| p.manage_channels());
+            if !manager {
+                return "only people who can manage the channel can change what it follows"
+                    .to_string();
+            }
+        }
+
+        match (command.data.name.as_str(), subreddit) {
+            ("bananagrabber-subscribe", Some(subreddit)) => {
+                let sort = match option("sort").and_then(|v| v.as_str()) {
+                    Some(sort) => match sort.parse::<ListingSort>() {
+                        Ok(sort) => sort,
+                        Err(e) => return e.to_string(),
+                    },
+                    None => ListingSort::New,
+                };
+                let min_score = option("min_score").and_then(|v| v.as_i64()).unwrap_or(0);
+                let sub = Subscription {
+                    guild: command.guild_id.map(|g| g.0).unwrap_or_default(),
+                    channel,
+                    subreddit: subreddit.clone(),
+                    sort,
+                    min_score,
+                };
+                let full = format!(
+                    "this server already follows {} subreddits, unsubscribe from one first",
+                    subscriptions::MAX_PER_GUILD
+                );
+                // don't spend reddit's rate limit on one that can't be added
+                if !self.subscriptions.has_room(&sub) {
+                    return full;
+                }
+                // reading the listing checks the subreddit is there, and gives
+                // the posts the channel shouldn't be flooded with
+                let current = match self
+                    .extractor
+                    .client()
+                    .subreddit_posts(&subreddit, sort)
+                    .await
+                {
+                    Ok(posts) => posts,
+                    Err(e) => {
+                        log::debug!("could not read r/{}: {:#}", subreddit, e);
+                        return format!("could not read r/{}", subreddit);
+                    }
+                };
+                if !self.subscriptions.subscribe(sub, &current).await {
+                    return full;
+                }
+                format!(
+                    "this channel now gets new media from r/{} ({}, score of at least {})",
+                    subreddit,
+                    sort.as_str(),
+                    min_score
+                )
+            }
+            ("bananagrabber-unsubscribe", Some(subreddit)) => {
+                if self.subscriptions.unsubscribe(channel, &subreddit).await {
+                    format!("this channel no longer follows r/{}", subreddit)
+                } else {
+                    format!("this channel doesn't follow r/{}", subreddit)
+                }
+            }
+            _ => {
+                let subs = self.subscriptions.for_channel(channel);
+                if subs.is_empty() {
+                    return "this channel doesn't follow any subreddits".to_string();
+                }
+                let lines: Vec<String> = subs
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "r/{} ({}, score of at least {})",
+                            s.subreddit,
+                            s.sort.as_str(),
+                            s.min_score
+                        )
+                    })
+                    .collect();
+                lines.join("\n")
+            }
+        }
+    }
+
+    /// Reply to a message that has reddit links in it, without being asked
+    async fn auto_reply(&self, ctx: &Context, msg: &Message) {
+        let links = reddit_links_in_text(&msg.content);
+        if links.is_empty() {
+            return;
+        }
+        // nobody asked, so there is nobody to tell about the cooldown
+        if self
+            .check_quota(msg.author.id, msg.channel_id, msg.guild_id)
+            .is_err()
+        {
+            return;
+        }
+        let content = match self.auto_content(&links).await {
+            Some(c) => c,
+            None => return,
+        };
+        let buttons = Buttons::new(links[0], msg.author.id.0);
+        let sent = msg
+            .channel_id
+            .send_message(&ctx.http, |m| {
+                m.content(content)
+                    .reference_message(msg)
+                    .allowed_mentions(|a| a.empty_parse().replied_user(false))
+                    .components(|c| buttons.build(c))
+            })
+            .await;
+        let reply = match sent {
+            Ok(reply) => reply,
+            Err(why) => {
+                log::error!("Error sending message: {:?}", why);
+                metrics::discord_error("send_message");
+                return;
+            }
+        };
+        self.tracker
+            .track(msg.channel_id.0, msg.id.0, reply.id.0, msg.author.id.0)
+            .await;
+        let emoji = ReactionType::Unicode(DELETE_EMOJI.to_string());
+        if let Err(why) = reply.react(&ctx.http, emoji).await {
+            log::warn!("could not add the delete reaction: {}", why);
+            metrics::discord_error("react");
+        }
+    }
+
+    /// The media behind the links, or nothing if there isn't any
+    async fn auto_content(&self, links: &[&str]) -> Option<String> {
+        let mut urls = Vec::new();
+        // one message is one request against the quota, however many links it has
+        for link in links.iter().take(MAX_EMBEDDED_MEDIA) {
+            match self.extractor.extract(link).await {
+                Ok(MediaOutcome::Found(media)) => urls.extend(media.into_iter().map(|m| m.url)),
+                // only media is worth replying to unasked
+                Ok(_) => {}
+                Err(e) => log::warn!("error while looking up {}: {}", link, e),
+            }
+        }
+        urls.truncate(MAX_EMBEDDED_MEDIA);
+        if urls.is_empty() {
+            None
+        } else {
+            Some(urls.join("\n"))
+        }
+    }
+
+    async fn delete_reply(&self, ctx: &Context, tracked: TrackedReply) {
+        let deleted = ChannelId(tracked.channel)
+            .delete_message(&ctx.http, tracked.reply)
+            .await;
+        if let Err(why) = deleted {
+            log::warn!("could not delete reply {}: {}", tracked.reply, why);
+            metrics::discord_error("delete_message");
+        }
+        self.tracker.forget(tracked.source).await;
+    }
+
+    /// Reply with the media links, paging through galleries one item at a time
+    async fn respond_with_media(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+        url: &str,
+    ) {
+        let mut buttons = Buttons::new(url, command.user.id.0);
+        let content = match self.extractor.extract(url).await {
+            Ok(MediaOutcome::Found(media)) if media.len() > 1 => {
+                buttons = buttons.paged(0, media.len());
+                components::page_content(&media, 0)
+            }
+            outcome => reply_text(url, outcome),
+        };
+
+        if let Err(why) = command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .content(content)
+                            .allowed_mentions(|a| a.empty_parse())
+                            .components(|c| buttons.build(c))
+                    })
+            })
+            .await
+        {
+            log::error!("Cannot respond to slash command: {}", why);
+            metrics::discord_error("respond");
+        }
+    }
+
+    /// Someone pressed one of the buttons under a reply
+    async fn press_button(&self, ctx: &Context, component: &MessageComponentInteraction) {
+        let action = match components::Action::parse(&component.data.custom_id) {
+            Some(a) => a,
+            None => return,
+        };
+        let buttons = match Buttons::from_message(&component.message) {
+            Some(b) => b,
+            None => {
+                log::warn!("button pressed on a message without our buttons");
+                return;
+            }
+        };
+
+        let responded = match action {
+            components::Action::Page(page) => {
+                let media = match buttons.post_url() {
+                    Some(url) => match self.extractor.extract(url).await {
+                        Ok(MediaOutcome::Found(media)) => media,
+                        _ => Vec::new(),
+                    },
+                    None => Vec::new(),
+                };
+                if page >= media.len() {
+                    ephemeral(ctx, component, "that post has changed, try asking again").await
+                } else {
+                    let buttons = buttons.paged(page, media.len());
+                    component
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::UpdateMessage)
+                                .interaction_response_data(|message| {
+                                    message
+                                        .content(components::page_content(&media, page))
+                                        .components(|c| buttons.build(c))
+                                })
+                        })
+                        .await
+                }
+            }
+            components::Action::Delete { requester } => {
+                let moderator = component
+                    .member
+                    .as_ref()
+                    .and_then(|m| m.permissions)
+                    .is_some_and(|p| p.manage_messages());
+                if component.user.id.0 == requester || moderator {
+                    let acked = component
+                        .create_interaction_response(&ctx.http, |response| {
+                            response.kind(InteractionResponseType::DeferredUpdateMessage)
+                        })
+                        .await;
+                    match acked {
+                        Ok(()) => component.message.delete(&ctx).await,
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    ephemeral(
+                        ctx,
+                        component,
+                        "only the person who asked, or a moderator, can delete this",
+                    )
+                    .await
+                }
+            }
+        };
+        if let Err(why) = responded {
+            log::error!("Cannot respond to button: {}", why);
+            metrics::discord_error("button");
+        }
+    }
+
+    /// Extraction and uploads can take longer than discord gives us to
+    /// answer, so defer the response and follow up with the media attached
+    async fn respond_with_uploads(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+        url: &str,
+    ) {
+        if let Err(why) = command.defer(&ctx.http).await {
+            log::error!("Cannot defer slash command: {}", why);
+            metrics::discord_error("respond");
+            return;
+        }
+
+        let buttons = Buttons::new(url, command.user.id.0);
+        let media = match self.extractor.extract(url).await {
+            Ok(MediaOutcome::Found(media)) => media,
+            outcome => {
+                let content = reply_text(url, outcome);
+                self.follow_up(ctx, command, content, Vec::new(), &buttons)
+                    .await;
+                return;
+            }
+        };
+
+        let limit = match command.guild_id {
+            Some(guild_id) => match guild_id.to_partial_guild(&ctx.http).await {
+                Ok(guild) => upload::upload_limit(guild.premium_tier),
+                Err(e) => {
+                    log::warn!("could not look up the boost tier of {}: {}", guild_id, e);
+                    upload::DEFAULT_UPLOAD_LIMIT
+                }
+            },
+            None => upload::DEFAULT_UPLOAD_LIMIT,
+        };
+
+        let mut budget = limit;
+        let mut files = Vec::new();
+        let mut links = Vec::new();
+        for (idx, m) in media.iter().take(MAX_EMBEDDED_MEDIA).enumerate() {
+            if m.kind() != MediaKind::Page {
+                match self.fetch_for_upload(m, budget).await {
+                    Ok(Some((data, ext))) => {
+                        budget -= data.len() as u64;
+                        files.push(AttachmentType::Bytes {
+                            data: data.into(),
+                            filename: format!("media{}.{}", idx + 1, ext),
+                        });
+                        continue;
+                    }
+                    Ok(None) => log::info!("{} does not fit in {} bytes", m.url, budget),
+                    Err(e) => log::warn!("could not fetch {} to upload: {:#}", m.url, e),
+                }
+            }
+            links.push(m.url.as_str());
+        }
+
+        let uploading = !files.is_empty();
+        let sent = self
+            .follow_up(ctx, command, links.join("\n"), files, &buttons)
+            .await;
+        if !sent && uploading {
+            // fall back to linking everything
+            let content = reply_text(url, Ok(MediaOutcome::Found(media)));
+            self.follow_up(ctx, command, content, Vec::new(), &buttons)
+                .await;
+        }
+    }
+
+    /// The media, if it fits in `cap` bytes, along with its file extension
+    async fn fetch_for_upload(
+        &self,
+        m: &Media,
+        cap: u64,
+    ) -> anyhow::Result<Option<(Vec<u8>, &'static str)>> {
+        let ext = match m.extension() {
+            Some(ext) => ext,
+            None => return Ok(None),
+        };
+        // ffmpeg opens the url itself, so check it here as well
+        upload::resolve_public(&Url::parse(&m.url)?).await?;
+        if let Some(data) = upload::fetch_capped(&m.url, cap).await? {
+            return Ok(Some((data, ext)));
+        }
+        #[cfg(feature = "ffmpeg")]
+        if let (Some(transcoder), MediaKind::Video) = (&self.transcoder, m.kind()) {
+            let shrunk = transcoder.shrink(&m.url, cap).await?;
+            // re-encodes always come out as mp4
+            return Ok(shrunk.map(|data| (data, "mp4")));
+        }
+        Ok(None)
+    }
+
+    async fn follow_up(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+        content: String,
+        files: Vec<AttachmentType<'static>>,
+        buttons: &Buttons,
+    ) -> bool {
+        let sent = command
+            .create_followup_message(&ctx.http, |message| {
+                if !content.is_empty() {
+                    message
+                        .content(&content)
+                        .allowed_mentions(|a| a.empty_parse());
+                }
+                message.add_files(files).components(|c| buttons.build(c))
+            })
+            .await;
+        if let Err(why) = &sent {
+            log::error!("Cannot follow up on slash command: {}", why);
+            metrics::discord_error("follow_up");
+        }
+        sent.is_ok()
+    }
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    // Set a handler for the `message` event - so that whenever a new message
+    // is received - the closure (or function) passed will be called.
+    //
+    // Event handlers are dispatched through a threadpool, and so multiple
+    // events can be dispatched simultaneously.
+    async fn message(&self, ctx: Context, msg: Message) {
+        let _work = match self.shutdown.start() {
+            Some(work) => work,
+            None => return,
+        };
+        if self.auto_detect && !msg.author.bot && msg.content != "!ping" {
+            self.auto_reply(&ctx, &msg).await;
+        }
+        if msg.content == "!ping" {
+            // Sending a message can fail, due to a network error, an
+            // authentication error, or lack of permissions to post in the
+            // channel, so log to stdout when some error happens, with a
+            // description of it.
+            let dm = msg
+                .author
+                .dm(&ctx, |m| m.content("You have requested ping!"))
+                .await;
+
+            if let Err(why) = dm {
+                log::error!("Error when direct messaging user: {:?}", why);
+                metrics::discord_error("dm");
+            }
+
+            if let Err(why) = msg.channel_id.say(&ctx.http, "Pong!").await {
+                log::error!("Error sending message: {:?}", why);
+                metrics::discord_error("send_message");
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            let _work = match self.shutdown.start() {
+                Some(work) => work,
+                None => {
+                    let _ = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    message.content("restarting, try again in a minute")
+                                })
+                        })
+                        .await;
+                    return;
+                }
+            };
+            let content = match command.data.name.as_str() {
+                "ping" => "Hey, I'm alive!".to_string(),
+                "id" => {
+                    let options = command
+                        .data
+                        .options
+                        .first()
+                        .expect("Expected user option")
+                        .resolved
+                        .as_ref()
+                        .expect("Expected user object");
+
+                    if let ApplicationCommandInteractionDataOptionValue::User(user, _member) =
+                        options
+                    {
+                        format!("{}'s id is {}", user.tag(), user.id)
+                    } else {
+                        "Please provide a valid user".to_string()
+                    }
+                }
+                "bananagrabber" => {
+                    let options = command
+                        .data
+                        .options
+                        .first()
+                        .expect("Expected user option")
+                        .resolved
+                        .as_ref()
+                        .expect("Expected user object");
+
+                    if let ApplicationCommandInteractionDataOptionValue::String(s) = options {
+                        let checked =
+                            self.check_quota(command.user.id, command.channel_id, command.guild_id);
+                        if let Err(cooldown) = checked {
+                            let responded = command
+                                .create_interaction_response(&ctx.http, |response| {
+                                    response
+                                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                                        .interaction_response_data(|message| {
+                                            message.content(cooldown.message()).flags(
+                                                InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                            )
+                                        })
+                                })
+                                .await;
+                            if let Err(why) = responded {
+                                log::error!("Cannot respond to slash command: {}", why);
+                                metrics::discord_error("respond");
+                            }
+                            return;
+                        }
+                        if self.upload_media {
+                            return self.respond_with_uploads(&ctx, &command, s).await;
+                        }
+                        return self.respond_with_media(&ctx, &command, s).await;
+                    } else {
+                        "please provide a url".to_string()
+                    }
+                }
+                "bananagrabber-settings" => self.guild_settings(&command).await,
+                "bananagrabber-subscribe"
+                | "bananagrabber-unsubscribe"
+                | "bananagrabber-subscriptions" => self.channel_subscriptions(&command).await,
+                _ => "not implemented :(".to_string(),
+            };
+
+            if let Err(why) = command
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| message.content(content))
+                })
+                .await
+            {
+                log::error!("Cannot respond to slash command: {}", why);
+                metrics::discord_error("respond");
+            }
+        } else if let Interaction::MessageComponent(component) = interaction {
+            if let Some(_work) = self.shutdown.start() {
+                self.press_button(&ctx, &component).await;
+            }
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        let _work = match self.shutdown.start() {
+            Some(work) => work,
+            None => return,
+        };
+        if let Some(tracked) = self.tracker.by_source(deleted_message_id.0) {
+            log::debug!("source {} was deleted, deleting its reply", tracked.source);
+            self.delete_reply(&ctx, tracked).await;
+        } else if let Some(tracked) = self.tracker.by_reply(deleted_message_id.0) {
+            self.tracker.forget(tracked.source).await;
+        }
+    }
+
+    async fn message_update(&self, ctx: Context, event: MessageUpdateEvent) {
+        let _work = match self.shutdown.start() {
+            Some(work) => work,
+            None => return,
+        };
+        let (tracked, content) = match (self.tracker.by_source(event.id.0), event.content) {
+            (Some(tracked), Some(content)) => (tracked, content),
+            _ => return,
+        };
+        if self
+            .check_quota(UserId(tracked.author), event.channel_id, event.guild_id)
+            .is_err()
+        {
+            return;
+        }
+        let links = reddit_links_in_text(&content);
+        let reply = match self.auto_content(&links).await {
+            Some(reply) => reply,
+            None => return self.delete_reply(&ctx, tracked).await,
+        };
+        let buttons = Buttons::new(links[0], tracked.author);
+        let edited = ChannelId(tracked.channel)
+            .edit_message(&ctx.http, tracked.reply, |m| {
+                m.content(reply).components(|c| buttons.build(c))
+            })
+            .await;
+        if let Err(why) = edited {
+            log::warn!("could not update reply {}: {}", tracked.reply, why);
+            metrics::discord_error("edit_message");
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        let _work = match self.shutdown.start() {
+            Some(work) => work,
+            None => return,
+        };
+        let is_delete = matches!(&reaction.emoji, ReactionType::Unicode(e)
+            if e.trim_end_matches('\u{fe0f}') == DELETE_EMOJI.trim_end_matches('\u{fe0f}'));
+        if !is_delete {
+            return;
+        }
+        if let Some(tracked) = self.tracker.by_reply(reaction.message_id.0) {
+            if reaction.user_id == Some(UserId(tracked.author)) {
+                self.delete_reply(&ctx, tracked).await;
+            }
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        let shard = ready.shard.map_or(0, |[shard, _]| shard);
+        log::info!(
+            "{} is connected on shard {}, in {} guilds",
+            ready.user.name,
+            shard,
+            ready.guilds.len()
+        );
+        self.readiness.shard_connected(shard, true);
+
+        // every shard gets a ready, but the commands only need setting once
+        if self.readiness.commands_registered.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // commands for one guild show up straight away, which is handy while
+        // developing, global ones can take a while to reach every guild
+        let commands = match env::var("GUILD_ID") {
+            Ok(guild_id) => {
+                let guild_id = GuildId(guild_id.parse().expect("GUILD_ID must be an integer"));
+                GuildId::set_application_commands(&guild_id, &ctx.http, create_commands).await
+            }
+            Err(_) => {
+                ApplicationCommand::set_global_application_commands(&ctx.http, create_commands)
+                    .await
+            }
+        };
+
+        // for x in ApplicationCommand::get_global_application_commands(&ctx.http).await {
+        //     log::info!("global app: {:#?}", x);
+        // }
+
+        // ApplicationCommand::delete_global_application_command(&ctx.http, 939674884773150770.into()).await;
+
+        match &commands {
+            Ok(_) => self
+                .readiness
+                .commands_registered
+                .store(true, Ordering::SeqCst),
+            Err(e) => {
+                log::error!("could not register commands: {}", e);
+                metrics::discord_error("register_commands");
+            }
+        }
+
+        log::debug!("I now have the following slash commands: {:#?}", commands);
+    }
+
+    async fn shard_stage_update(&self, _ctx: Context, event: ShardStageUpdateEvent) {
+        log::info!(
+            "shard {} is {} (was {})",
+            event.shard_id,
+            event.new,
+            event.old
+        );
+        self.readiness
+            .shard_connected(event.shard_id.0, event.new == ConnectionStage::Connected);
+    }
+}
+
+fn create_commands(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        // .create_application_command(|command| {
+        //     command.name("ping").description("A ping command")
+        // })
+        // .create_application_command(|command| {
+        //     command
+        //         .name("id")
+        //         .description("Get a user id")
+        //         .create_option(|option| {
+        //             option
+        //                 .name("id")
+        //                 .description("The user to lookup")
+        //                 .kind(ApplicationCommandOptionType::User)
+        //                 .required(true)
+        //         })
+        // })
+        .create_application_command(|command| {
+            command
+                .name("bananagrabber")
+                .description("Extract the media out of a reddit link")
+                .create_option(|option| {
+                    option
+                        .name("url")
+                        .description("The reddit link")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bananagrabber-settings")
+                .description("Show or change how often the bot can be asked for media")
+                .create_option(|option| {
+                    option
+                        .name("scope")
+                        .description("Which quota to change")
+                        .kind(ApplicationCommandOptionType::String)
+                        .add_string_choice("per user", "user")
+                        .add_string_choice("per channel", "channel")
+                        .add_string_choice("whole server", "guild")
+                })
+                .create_option(|option| {
+                    option
+                        .name("quota")
+                        .description("requests/seconds, like 5/60, or off")
+                        .kind(ApplicationCommandOptionType::String)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bananagrabber-subscribe")
+                .description("Post new media from a subreddit to this channel")
+                .create_option(|option| {
+                    option
+                        .name("subreddit")
+                        .description("The subreddit to follow, like gifs")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+                .create_option(|option| {
+                    option
+                        .name("sort")
+                        .description("Which posts to look at, new ones by default")
+                        .kind(ApplicationCommandOptionType::String)
+                        .add_string_choice("new", "new")
+                        .add_string_choice("hot", "hot")
+                        .add_string_choice("top of the day", "top")
+                })
+                .create_option(|option| {
+                    option
+                        .name("min_score")
+                        .description("Skip posts with a lower score")
+                        .kind(ApplicationCommandOptionType::Integer)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bananagrabber-unsubscribe")
+                .description("Stop posting media from a subreddit to this channel")
+                .create_option(|option| {
+                    option
+                        .name("subreddit")
+                        .description("The subreddit to stop following")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("bananagrabber-subscriptions")
+                .description("List the subreddits this channel follows")
+        })
+}
+
+/// Answer only the user who pressed the button
+async fn ephemeral(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    content: &str,
+) -> serenity::Result<()> {
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// The reply to an extraction, as plain text
+fn reply_text(url: &str, outcome: anyhow::Result<MediaOutcome>) -> String {
+    match outcome {
+        // discord will embed each link, so albums show up as a group
+        Ok(MediaOutcome::Found(media)) => media
+            .iter()
+            .take(MAX_EMBEDDED_MEDIA)
+            .map(|m| m.url.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Ok(MediaOutcome::Unavailable(u)) => format!("that post is {}", u),
+        Ok(MediaOutcome::Text(q)) => format_quote(&q),
+        Ok(MediaOutcome::NotFound) => url.to_string(),
+        Err(e) => {
+            log::error!("error while looking up url: {}", e);
+            url.to_string()
+        }
+    }
+}
+
+/// Show the text of a self post as a block quote
+fn format_quote(quote: &Quote) -> String {
+    let quoted: Vec<String> = quote.text.lines().map(|l| format!("> {}", l)).collect();
+    let formatted = format!("**{}**\n{}", quote.title, quoted.join("\n"));
+    if formatted.chars().count() <= MAX_MESSAGE_LEN {
+        return formatted;
+    }
+    let mut cut: String = formatted.chars().take(MAX_MESSAGE_LEN - 1).collect();
+    cut.push('…');
+    cut
+}
+
+pub async fn bot_start(extractor: Extractor, config: BotConfig) -> anyhow::Result<()> {
+    // Configure the client with your Discord bot token in the environment.
+    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    //
+    // The Application Id is usually the Bot User Id.
+    let application_id: u64 = env::var("APPLICATION_ID")
+        .expect("Expected an application id in the environment")
+        .parse()
+        .expect("application id is not a valid id");
+
+    // Create a new instance of the Client, logging in as a bot. This will
+    // automatically prepend your bot token with "Bot ", which is a requirement
+    // by Discord for bot users.
+    let readiness = Arc::new(Readiness::default());
+    let tracker = match &config.reply_state {
+        Some(path) => ReplyTracker::load(path.clone(), config.reply_window)?,
+        None => ReplyTracker::in_memory(config.reply_window),
+    };
+    let settings = match &config.guild_settings {
+        Some(path) => SettingsStore::load(path.clone())?,
+        None => SettingsStore::in_memory(),
+    };
+    let subscriptions = Arc::new(match &config.subscriptions {
+        Some(path) => SubscriptionStore::load(path.clone())?,
+        None => SubscriptionStore::in_memory(),
+    });
+    let shutdown = Arc::new(shutdown::Shutdown::default());
+    let extractor = Arc::new(extractor);
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = config.health_addr {
+        let readiness = readiness.clone();
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(addr, readiness).await {
+                log::error!("health listener stopped: {}", e);
+            }
+        });
+    }
+
+    let mut client = Client::builder(&token)
+        .event_handler(Handler {
+            extractor: extractor.clone(),
+            readiness,
+            shutdown: shutdown.clone(),
+            upload_media: config.upload_media,
+            auto_detect: config.auto_detect,
+            tracker,
+            quotas: config.quotas,
+            throttle: Throttle::default(),
+            settings,
+            subscriptions: subscriptions.clone(),
+            #[cfg(feature = "ffmpeg")]
+            transcoder: config.transcode.map(transcode::Transcoder::new),
+        })
+        .application_id(application_id)
+        .await
+        .expect("Err creating client");
+
+    tokio::spawn(subscriptions::poll(
+        client.cache_and_http.http.clone(),
+        extractor.clone(),
+        subscriptions,
+        shutdown.clone(),
+        config.poll_interval,
+    ));
+
+    // On SIGTERM, stop taking events, let the ones being handled finish,
+    // then close the gateway connection, which makes `start` return.
+    let shard_manager = client.shard_manager.clone();
+    let deadline = config.shutdown_deadline;
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        log::info!(
+            "shutting down, waiting up to {:?} for in-flight work",
+            deadline
+        );
+        let left = shutdown.drain(deadline).await;
+        if left > 0 {
+            log::warn!("giving up on {} events still being handled", left);
+        }
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
+    // Finally, start the shards, and start listening to events.
+    //
+    // Shards will automatically attempt to reconnect, and will perform
+    // exponential backoff until it reconnects.
+    log::info!("starting shards: {}", config.sharding);
+    match config.sharding {
+        Sharding::Single => client.start().await?,
+        Sharding::Auto => client.start_autosharded().await?,
+        Sharding::Range { first, last, total } => {
+            client.start_shard_range([first, last], total).await?
+        }
+    }
+
+    extractor.flush();
+    #[cfg(feature = "metrics")]
+    if let Some(path) = &config.metrics_file {
+        std::fs::write(path, metrics::render())?;
+    }
+    log::info!("bot stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_fits_in_a_message() {
+        let quote = Quote {
+            title: "t".repeat(300),
+            text: "a\n".repeat(1000),
+        };
+        let formatted = format_quote(&quote);
+        assert_eq!(formatted.chars().count(), MAX_MESSAGE_LEN);
+        assert!(formatted.ends_with('…'));
+
+        let short = Quote {
+            title: "title".to_string(),
+            text: "one\ntwo".to_string(),
+        };
+        assert_eq!(format_quote(&short), "**title**\n> one\n> two");
+    }
+}
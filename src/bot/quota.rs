@@ -0,0 +1,312 @@
+//! Limits on how often the bot can be asked for media, so one busy user,
+//! channel or guild can't use up the reddit rate limit everyone shares.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Forget about keys nobody has used in a while, once there are this many
+const PRUNE_AT: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    User,
+    Channel,
+    Guild,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::User, Scope::Channel, Scope::Guild];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::Channel => "channel",
+            Scope::Guild => "guild",
+        }
+    }
+}
+
+/// At most `requests` every `per_secs` seconds, no limit when `requests` is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub requests: u32,
+    pub per_secs: u64,
+}
+
+impl Quota {
+    pub const OFF: Quota = Quota {
+        requests: 0,
+        per_secs: 0,
+    };
+
+    pub fn new(requests: u32, per_secs: u64) -> Quota {
+        Quota { requests, per_secs }
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.per_secs)
+    }
+
+    /// Whether this allows no more than `limit`: as many requests or fewer,
+    /// over as long a window or longer
+    pub fn within(&self, limit: Quota) -> bool {
+        if limit.requests == 0 {
+            return true;
+        }
+        self.requests != 0 && self.requests <= limit.requests && self.per_secs >= limit.per_secs
+    }
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.requests == 0 {
+            write!(f, "off")
+        } else {
+            write!(f, "{}/{}", self.requests, self.per_secs)
+        }
+    }
+}
+
+/// `<requests>/<seconds>`, or `off`
+impl FromStr for Quota {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Quota> {
+        if s == "off" {
+            return Ok(Quota::OFF);
+        }
+        let (requests, per_secs) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("expected <requests>/<seconds>, or off"))?;
+        let quota = Quota::new(requests.parse()?, per_secs.parse()?);
+        if quota.requests > 0 && quota.per_secs == 0 {
+            anyhow::bail!("the quota needs a period of at least a second");
+        }
+        Ok(quota)
+    }
+}
+
+/// The quota for each scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quotas {
+    pub user: Quota,
+    pub channel: Quota,
+    pub guild: Quota,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Quotas {
+            user: Quota::new(5, 60),
+            channel: Quota::new(20, 60),
+            guild: Quota::new(60, 60),
+        }
+    }
+}
+
+impl Quotas {
+    pub fn get(&self, scope: Scope) -> Quota {
+        match scope {
+            Scope::User => self.user,
+            Scope::Channel => self.channel,
+            Scope::Guild => self.guild,
+        }
+    }
+
+    pub fn set(&mut self, scope: Scope, quota: Quota) {
+        match scope {
+            Scope::User => self.user = quota,
+            Scope::Channel => self.channel = quota,
+            Scope::Guild => self.guild = quota,
+        }
+    }
+}
+
+/// A request that went over a quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Cooldown {
+    pub scope: Scope,
+    pub retry_after: Duration,
+}
+
+impl Cooldown {
+    /// What to tell the user
+    pub(super) fn message(&self) -> String {
+        let secs = self.retry_after.as_secs().max(1);
+        match self.scope {
+            Scope::User => format!("slow down, you can ask again in {}s", secs),
+            Scope::Channel => format!("this channel is busy, try again in {}s", secs),
+            Scope::Guild => format!("this server is busy, try again in {}s", secs),
+        }
+    }
+}
+
+/// The requests by one user, channel or guild within its window
+#[derive(Debug, Default)]
+struct Recent {
+    /// The window of the quota this was last checked against, which
+    /// differs between guilds
+    window: Duration,
+    times: VecDeque<Instant>,
+}
+
+/// The recent requests by each user, channel and guild
+#[derive(Debug, Default)]
+pub(super) struct Throttle {
+    recent: Mutex<HashMap<(Scope, u64), Recent>>,
+}
+
+impl Throttle {
+    /// Count a request against every key, unless one of them is over its quota
+    pub(super) fn check(
+        &self,
+        quotas: &Quotas,
+        keys: &[(Scope, u64)],
+        now: Instant,
+    ) -> Result<(), Cooldown> {
+        let mut recent = self.recent.lock().unwrap();
+        for &(scope, id) in keys {
+            let quota = quotas.get(scope);
+            if quota.requests == 0 {
+                continue;
+            }
+            if let Some(Recent { times, .. }) = recent.get_mut(&(scope, id)) {
+                while times
+                    .front()
+                    .is_some_and(|&t| now.duration_since(t) >= quota.window())
+                {
+                    times.pop_front();
+                }
+                if times.len() >= quota.requests as usize {
+                    let oldest = times[0];
+                    return Err(Cooldown {
+                        scope,
+                        retry_after: quota.window() - now.duration_since(oldest),
+                    });
+                }
+            }
+        }
+
+        for &(scope, id) in keys {
+            let quota = quotas.get(scope);
+            if quota.requests > 0 {
+                let entry = recent.entry((scope, id)).or_default();
+                entry.window = quota.window();
+                entry.times.push_back(now);
+            }
+        }
+        if recent.len() > PRUNE_AT {
+            recent.retain(|_, r| {
+                r.times
+                    .back()
+                    .is_some_and(|&t| now.duration_since(t) < r.window)
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_quota() {
+        assert_eq!("5/60".parse::<Quota>().unwrap(), Quota::new(5, 60));
+        assert_eq!("off".parse::<Quota>().unwrap(), Quota::OFF);
+        assert!("5".parse::<Quota>().is_err());
+        assert!("5/0".parse::<Quota>().is_err());
+    }
+
+    #[test]
+    fn quota_within_limit() {
+        let limit = Quota::new(5, 60);
+        assert!(Quota::new(2, 60).within(limit));
+        assert!(Quota::new(5, 3600).within(limit));
+        assert!(!Quota::new(1, 10).within(limit));
+        assert!(!Quota::new(10, 60).within(limit));
+        assert!(!Quota::OFF.within(limit));
+        assert!(Quota::OFF.within(Quota::OFF));
+    }
+
+    #[test]
+    fn user_cooldown() {
+        let quotas = Quotas {
+            user: Quota::new(2, 60),
+            channel: Quota::OFF,
+            guild: Quota::OFF,
+        };
+        let throttle = Throttle::default();
+        let now = Instant::now();
+        let alice = [(Scope::User, 1), (Scope::Channel, 10)];
+        let bob = [(Scope::User, 2), (Scope::Channel, 10)];
+
+        assert!(throttle.check(&quotas, &alice, now).is_ok());
+        assert!(throttle.check(&quotas, &alice, now).is_ok());
+        let later = now + Duration::from_secs(15);
+        assert_eq!(
+            throttle.check(&quotas, &alice, later),
+            Err(Cooldown {
+                scope: Scope::User,
+                retry_after: Duration::from_secs(45)
+            })
+        );
+        assert!(throttle.check(&quotas, &bob, later).is_ok());
+        assert!(throttle
+            .check(&quotas, &alice, now + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn pruning_keeps_longer_windows() {
+        let hourly = Quotas {
+            user: Quota::new(1, 3600),
+            channel: Quota::OFF,
+            guild: Quota::OFF,
+        };
+        let throttle = Throttle::default();
+        let now = Instant::now();
+        assert!(throttle.check(&hourly, &[(Scope::User, 1)], now).is_ok());
+
+        // lots of users in a guild with short windows, a few minutes later
+        let later = now + Duration::from_secs(300);
+        for user in 2..(PRUNE_AT as u64 + 10) {
+            assert!(throttle
+                .check(&Quotas::default(), &[(Scope::User, user)], later)
+                .is_ok());
+        }
+        assert_eq!(
+            throttle
+                .check(&hourly, &[(Scope::User, 1)], later)
+                .unwrap_err()
+                .scope,
+            Scope::User
+        );
+    }
+
+    #[test]
+    fn refused_requests_are_not_counted() {
+        let quotas = Quotas {
+            user: Quota::new(5, 60),
+            channel: Quota::new(1, 60),
+            guild: Quota::OFF,
+        };
+        let throttle = Throttle::default();
+        let now = Instant::now();
+        assert!(throttle
+            .check(&quotas, &[(Scope::User, 1), (Scope::Channel, 10)], now)
+            .is_ok());
+        for _ in 0..10 {
+            let refused = throttle.check(&quotas, &[(Scope::User, 1), (Scope::Channel, 10)], now);
+            assert_eq!(refused.unwrap_err().scope, Scope::Channel);
+        }
+        // alice still has quota left in another channel
+        assert!(throttle
+            .check(&quotas, &[(Scope::User, 1), (Scope::Channel, 11)], now)
+            .is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+//! What each guild has changed about how the bot behaves there, set with
+//! `/bananagrabber-settings` and kept in a json file when one is configured.
+use super::json_state::StateFile;
+use super::quota::{Quota, Quotas, Scope};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct GuildSettings {
+    /// Quotas that replace the bot's defaults in this guild, which can only
+    /// be tighter than them
+    #[serde(default)]
+    pub quotas: HashMap<Scope, Quota>,
+}
+
+impl GuildSettings {
+    pub(super) fn quotas(&self, defaults: &Quotas) -> Quotas {
+        let mut quotas = *defaults;
+        for (&scope, &quota) in &self.quotas {
+            // the defaults may have been tightened since this was saved
+            if quota.within(defaults.get(scope)) {
+                quotas.set(scope, quota);
+            }
+        }
+        quotas
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct SettingsStore {
+    file: Option<StateFile>,
+    guilds: Arc<Mutex<HashMap<u64, GuildSettings>>>,
+}
+
+impl SettingsStore {
+    pub(super) fn in_memory() -> SettingsStore {
+        SettingsStore {
+            file: None,
+            guilds: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(super) fn load(path: PathBuf) -> anyhow::Result<SettingsStore> {
+        let file = StateFile::new(path);
+        let guilds = file.load()?;
+        Ok(SettingsStore {
+            file: Some(file),
+            guilds: Arc::new(Mutex::new(guilds)),
+        })
+    }
+
+    pub(super) fn get(&self, guild: u64) -> GuildSettings {
+        self.guilds
+            .lock()
+            .unwrap()
+            .get(&guild)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Change a guild's settings, and save them
+    pub(super) async fn update<F: FnOnce(&mut GuildSettings)>(
+        &self,
+        guild: u64,
+        f: F,
+    ) -> anyhow::Result<GuildSettings> {
+        let updated = {
+            let mut guilds = self.guilds.lock().unwrap();
+            let settings = guilds.entry(guild).or_default();
+            f(settings);
+            settings.clone()
+        };
+        if let Some(file) = &self.file {
+            let guilds = self.guilds.clone();
+            file.save(move || guilds.lock().unwrap().clone()).await?;
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::json_state;
+
+    #[tokio::test]
+    async fn overrides_survive_a_restart() {
+        let path = json_state::test_path("settings");
+        let store = SettingsStore::load(path.clone()).unwrap();
+        store
+            .update(7, |s| {
+                s.quotas.insert(Scope::User, Quota::new(1, 60));
+            })
+            .await
+            .unwrap();
+
+        let reloaded = SettingsStore::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let quotas = reloaded.get(7).quotas(&Quotas::default());
+        assert_eq!(quotas.user, Quota::new(1, 60));
+        assert_eq!(quotas.channel, Quotas::default().channel);
+        assert_eq!(reloaded.get(8), GuildSettings::default());
+    }
+
+    #[test]
+    fn overrides_only_tighten() {
+        let mut settings = GuildSettings::default();
+        settings.quotas.insert(Scope::User, Quota::new(1, 60));
+        settings.quotas.insert(Scope::Guild, Quota::OFF);
+        let quotas = settings.quotas(&Quotas::default());
+        assert_eq!(quotas.user, Quota::new(1, 60));
+        assert_eq!(quotas.guild, Quotas::default().guild);
+    }
+}
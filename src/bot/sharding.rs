@@ -0,0 +1,99 @@
+//! Which of the bot's shards this process runs, so the guilds can be split
+//! across several instances.
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sharding {
+    /// One shard, for every guild
+    #[default]
+    Single,
+    /// As many shards as discord recommends, all in this process
+    Auto,
+    /// Shards `first` to `last` (inclusive), out of `total`
+    Range { first: u64, last: u64, total: u64 },
+}
+
+impl fmt::Display for Sharding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sharding::Single => write!(f, "single"),
+            Sharding::Auto => write!(f, "auto"),
+            Sharding::Range { first, last, total } => write!(f, "{}-{}/{}", first, last, total),
+        }
+    }
+}
+
+/// `auto`, a total number of shards to run (`8`), or a range of them out of
+/// a total (`0-3/8`, or `4/8` for just one)
+impl FromStr for Sharding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Sharding> {
+        if s == "auto" {
+            return Ok(Sharding::Auto);
+        }
+        if s == "single" {
+            return Ok(Sharding::Single);
+        }
+        let (range, total) = match s.split_once('/') {
+            Some((range, total)) => (Some(range), total),
+            None => (None, s),
+        };
+        let total: u64 = total.parse()?;
+        if total == 0 {
+            anyhow::bail!("there must be at least one shard");
+        }
+        let (first, last) = match range {
+            None => (0, total - 1),
+            Some(range) => match range.split_once('-') {
+                Some((first, last)) => (first.parse()?, last.parse()?),
+                None => {
+                    let shard = range.parse()?;
+                    (shard, shard)
+                }
+            },
+        };
+        if first > last || last >= total {
+            anyhow::bail!("shards {}-{} are not within 0-{}", first, last, total - 1);
+        }
+        Ok(Sharding::Range { first, last, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sharding() {
+        assert_eq!("auto".parse::<Sharding>().unwrap(), Sharding::Auto);
+        assert_eq!(
+            "8".parse::<Sharding>().unwrap(),
+            Sharding::Range {
+                first: 0,
+                last: 7,
+                total: 8
+            }
+        );
+        assert_eq!(
+            "4-7/8".parse::<Sharding>().unwrap(),
+            Sharding::Range {
+                first: 4,
+                last: 7,
+                total: 8
+            }
+        );
+        assert_eq!(
+            "2/8".parse::<Sharding>().unwrap(),
+            Sharding::Range {
+                first: 2,
+                last: 2,
+                total: 8
+            }
+        );
+        assert!("6-9/8".parse::<Sharding>().is_err());
+        assert!("0".parse::<Sharding>().is_err());
+        assert!("lots".parse::<Sharding>().is_err());
+    }
+}
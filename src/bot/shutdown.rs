@@ -0,0 +1,109 @@
+//! Stopping the bot without cutting off the extractions it is in the middle of.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(20);
+
+/// Tracks the events being handled, and whether new ones should be
+#[derive(Debug, Default)]
+pub(super) struct Shutdown {
+    stopping: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// Held while an event is being handled
+pub(super) struct Work(Arc<Shutdown>);
+
+impl Drop for Work {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Shutdown {
+    /// Start handling an event, unless the bot is shutting down
+    pub(super) fn start(self: &Arc<Self>) -> Option<Work> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let work = Work(self.clone());
+        if self.stopping.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(work)
+    }
+
+    /// Stop taking new events, and wait up to `deadline` for the rest to
+    /// finish. Returns how many were still going at the deadline.
+    pub(super) async fn drain(&self, deadline: Duration) -> usize {
+        self.stopping.store(true, Ordering::SeqCst);
+        let drained = tokio::time::timeout(deadline, async {
+            loop {
+                let idle = self.idle.notified();
+                if self.in_flight.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await;
+        match drained {
+            Ok(()) => 0,
+            Err(_) => self.in_flight.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Wait for SIGINT, or SIGTERM where there is such a thing
+pub(super) async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => log::info!("got SIGTERM"),
+                    _ = tokio::signal::ctrl_c() => log::info!("got SIGINT"),
+                }
+                return;
+            }
+            Err(e) => log::warn!("can't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("can't listen for SIGINT: {}", e);
+        std::future::pending::<()>().await;
+    }
+    log::info!("got SIGINT");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_waits_for_work() {
+        let shutdown = Arc::new(Shutdown::default());
+        let work = shutdown.start().unwrap();
+
+        assert_eq!(shutdown.drain(Duration::from_millis(10)).await, 1);
+        assert!(shutdown.start().is_none());
+
+        let waiting = {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { shutdown.drain(Duration::from_secs(5)).await })
+        };
+        drop(work);
+        assert_eq!(waiting.await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_when_idle() {
+        let shutdown = Arc::new(Shutdown::default());
+        assert_eq!(shutdown.drain(Duration::from_secs(5)).await, 0);
+        assert!(shutdown.start().is_none());
+    }
+}
@@ -0,0 +1,359 @@
+//! Channels that follow a subreddit, getting its new media posts as they
+//! show up.
+//!
+//! A poller reads each subreddit's listing through the shared reddit client,
+//! so it stays inside the rate limit, and posts anything it hasn't posted to
+//! the channel before.
+use super::components::{self, Buttons};
+use super::json_state::StateFile;
+use super::shutdown::Shutdown;
+use crate::media_extraction::{Extractor, MediaOutcome};
+use crate::metrics;
+use crate::reddit::{ListedPost, ListingSort};
+use serde::{Deserialize, Serialize};
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Remember this many posted ids per channel, more than a listing holds
+const POSTED_PER_CHANNEL: usize = 500;
+/// How many subreddits the channels of one guild can follow between them,
+/// since every one of them is read from reddit each poll
+pub const MAX_PER_GUILD: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Subscription {
+    /// Zero for subscriptions saved before guilds were kept
+    #[serde(default)]
+    pub guild: u64,
+    pub channel: u64,
+    pub subreddit: String,
+    pub sort: ListingSort,
+    /// Only posts with at least this score are posted
+    pub min_score: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct State {
+    subscriptions: Vec<Subscription>,
+    /// The ids of the posts already sent to each channel, oldest first
+    posted: HashMap<u64, VecDeque<String>>,
+}
+
+#[derive(Debug)]
+pub(super) struct SubscriptionStore {
+    file: Option<StateFile>,
+    state: Arc<Mutex<State>>,
+}
+
+impl SubscriptionStore {
+    pub(super) fn in_memory() -> SubscriptionStore {
+        SubscriptionStore {
+            file: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    pub(super) fn load(path: PathBuf) -> anyhow::Result<SubscriptionStore> {
+        let file = StateFile::new(path);
+        let state = file.load()?;
+        Ok(SubscriptionStore {
+            file: Some(file),
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Whether the guild can follow `sub`, which changing how a channel
+    /// follows a subreddit always can
+    pub(super) fn has_room(&self, sub: &Subscription) -> bool {
+        let state = self.state.lock().unwrap();
+        has_room(&state, sub)
+    }
+
+    /// Follow a subreddit, or change how it is followed. The posts already
+    /// in the listing count as posted, so the channel isn't flooded.
+    ///
+    /// Returns false, changing nothing, when the guild is already following
+    /// `MAX_PER_GUILD` subreddits.
+    pub(super) async fn subscribe(&self, sub: Subscription, current: &[ListedPost]) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            if !has_room(&state, &sub) {
+                return false;
+            }
+            state.subscriptions.retain(|s| !s.same_as(&sub));
+            let posted = state.posted.entry(sub.channel).or_default();
+            for post in current {
+                remember(posted, &post.id);
+            }
+            state.subscriptions.push(sub);
+        }
+        self.save().await;
+        true
+    }
+
+    /// Returns whether the channel was following the subreddit
+    pub(super) async fn unsubscribe(&self, channel: u64, subreddit: &str) -> bool {
+        let removed = {
+            let mut state = self.state.lock().unwrap();
+            let before = state.subscriptions.len();
+            state
+                .subscriptions
+                .retain(|s| !(s.channel == channel && s.subreddit.eq_ignore_ascii_case(subreddit)));
+            if !state.subscriptions.iter().any(|s| s.channel == channel) {
+                state.posted.remove(&channel);
+            }
+            state.subscriptions.len() < before
+        };
+        self.save().await;
+        removed
+    }
+
+    pub(super) fn for_channel(&self, channel: u64) -> Vec<Subscription> {
+        let state = self.state.lock().unwrap();
+        state
+            .subscriptions
+            .iter()
+            .filter(|s| s.channel == channel)
+            .cloned()
+            .collect()
+    }
+
+    fn all(&self) -> Vec<Subscription> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+
+    /// The posts in a listing worth sending to the subscribed channel, that
+    /// haven't been sent to it yet
+    fn fresh(&self, sub: &Subscription, listing: &[ListedPost]) -> Vec<ListedPost> {
+        let state = self.state.lock().unwrap();
+        let posted = state.posted.get(&sub.channel);
+        listing
+            .iter()
+            .filter(|p| !p.stickied && !p.nsfw && p.score >= sub.min_score)
+            .filter(|p| !posted.is_some_and(|posted| posted.contains(&p.id)))
+            .cloned()
+            .collect()
+    }
+
+    /// Count a post as sent to the channel, so it isn't sent again
+    async fn posted(&self, channel: u64, id: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            remember(state.posted.entry(channel).or_default(), id);
+        }
+        self.save().await;
+    }
+
+    async fn save(&self) {
+        let file = match &self.file {
+            Some(f) => f,
+            None => return,
+        };
+        let state = self.state.clone();
+        let saved = file.save(move || state.lock().unwrap().clone()).await;
+        if let Err(e) = saved {
+            log::warn!(
+                "could not save subscriptions to {}: {}",
+                file.path().display(),
+                e
+            );
+        }
+    }
+}
+
+impl Subscription {
+    fn same_as(&self, other: &Subscription) -> bool {
+        self.channel == other.channel && self.subreddit.eq_ignore_ascii_case(&other.subreddit)
+    }
+}
+
+fn has_room(state: &State, sub: &Subscription) -> bool {
+    let others = state
+        .subscriptions
+        .iter()
+        .filter(|s| s.guild == sub.guild && !s.same_as(sub))
+        .count();
+    others < MAX_PER_GUILD
+}
+
+fn remember(posted: &mut VecDeque<String>, id: &str) {
+    posted.push_back(id.to_string());
+    while posted.len() > POSTED_PER_CHANNEL {
+        posted.pop_front();
+    }
+}
+
+/// Check every subscription each `interval`, until the bot shuts down
+pub(super) async fn poll(
+    http: Arc<Http>,
+    extractor: Arc<Extractor>,
+    store: Arc<SubscriptionStore>,
+    shutdown: Arc<Shutdown>,
+    interval: Duration,
+) {
+    // a zero interval would panic, and hammer reddit besides
+    let mut ticks = tokio::time::interval(interval.max(Duration::from_secs(1)));
+    loop {
+        ticks.tick().await;
+        let _work = match shutdown.start() {
+            Some(work) => work,
+            None => return,
+        };
+
+        // read each listing once, however many channels follow it
+        let mut listings: HashMap<(String, ListingSort), Vec<ListedPost>> = HashMap::new();
+        for sub in store.all() {
+            let key = (sub.subreddit.to_ascii_lowercase(), sub.sort);
+            if !listings.contains_key(&key) {
+                match extractor
+                    .client()
+                    .subreddit_posts(&sub.subreddit, sub.sort)
+                    .await
+                {
+                    Ok(posts) => {
+                        listings.insert(key.clone(), posts);
+                    }
+                    Err(e) => {
+                        log::warn!("could not read r/{}: {:#}", sub.subreddit, e);
+                        continue;
+                    }
+                }
+            }
+            // listings are newest first, post the oldest first
+            for post in store.fresh(&sub, &listings[&key]).iter().rev() {
+                // whatever couldn't be sent is tried again on the next poll
+                if send_post(&http, &extractor, &sub, post).await {
+                    store.posted(sub.channel, &post.id).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the post is done with, either sent or without any media
+/// to send
+async fn send_post(
+    http: &Http,
+    extractor: &Extractor,
+    sub: &Subscription,
+    post: &ListedPost,
+) -> bool {
+    let media = match extractor.extract(&post.url).await {
+        Ok(MediaOutcome::Found(media)) if !media.is_empty() => media,
+        Ok(_) => return true,
+        Err(e) => {
+            log::warn!("error while looking up {}: {}", post.url, e);
+            return false;
+        }
+    };
+    // nobody asked for it, so only moderators can delete it
+    let buttons = Buttons::new(&post.url, 0).paged(0, media.len());
+    let content = format!(
+        "**{}** (r/{})\n{}",
+        post.title,
+        sub.subreddit,
+        components::page_content(&media, 0)
+    );
+    let sent = ChannelId(sub.channel)
+        .send_message(http, |m| {
+            m.content(content)
+                .allowed_mentions(|a| a.empty_parse())
+                .components(|c| buttons.build(c))
+        })
+        .await;
+    match sent {
+        Ok(_) => true,
+        Err(why) => {
+            log::error!("could not post to channel {}: {}", sub.channel, why);
+            metrics::discord_error("send_message");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::json_state;
+
+    fn post(id: &str, score: i64) -> ListedPost {
+        ListedPost {
+            id: id.to_string(),
+            title: id.to_string(),
+            score,
+            nsfw: false,
+            stickied: false,
+            url: format!("https://www.reddit.com/r/gifs/comments/{}/", id),
+        }
+    }
+
+    fn sub(channel: u64) -> Subscription {
+        Subscription {
+            guild: 7,
+            channel,
+            subreddit: "gifs".to_string(),
+            sort: ListingSort::New,
+            min_score: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn only_new_posts_over_the_score() {
+        let store = SubscriptionStore::in_memory();
+        store.subscribe(sub(1), &[post("a", 50)]).await;
+
+        let listing = [post("c", 20), post("b", 5), post("a", 50)];
+        let fresh: Vec<String> = store
+            .fresh(&sub(1), &listing)
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(fresh, ["c"]);
+        // until it has been sent
+        assert_eq!(store.fresh(&sub(1), &listing).len(), 1);
+        store.posted(1, "c").await;
+        assert!(store.fresh(&sub(1), &listing).is_empty());
+        // another channel gets them too
+        assert_eq!(store.fresh(&sub(2), &listing).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn subscriptions_survive_a_restart() {
+        let path = json_state::test_path("subscriptions");
+        let store = SubscriptionStore::load(path.clone()).unwrap();
+        store.subscribe(sub(1), &[post("a", 50)]).await;
+        store.subscribe(sub(2), &[]).await;
+        assert!(store.unsubscribe(2, "GIFS").await);
+        assert!(!store.unsubscribe(2, "gifs").await);
+
+        let reloaded = SubscriptionStore::load(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.for_channel(1), [sub(1)]);
+        assert!(reloaded.for_channel(2).is_empty());
+        assert!(reloaded.fresh(&sub(1), &[post("a", 50)]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscriptions_per_guild_are_capped() {
+        let store = SubscriptionStore::in_memory();
+        for channel in 0..MAX_PER_GUILD as u64 {
+            assert!(store.subscribe(sub(channel), &[]).await);
+        }
+        assert!(!store.has_room(&sub(99)));
+        assert!(!store.subscribe(sub(99), &[]).await);
+        assert!(store.for_channel(99).is_empty());
+        // changing a subscription doesn't need more room
+        assert!(store.subscribe(sub(0), &[]).await);
+        // and other guilds have their own
+        let other = Subscription {
+            guild: 8,
+            ..sub(99)
+        };
+        assert!(store.subscribe(other, &[]).await);
+    }
+}
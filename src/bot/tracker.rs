@@ -0,0 +1,159 @@
+//! Which of the bot's replies belongs to which message, so a reply can
+//! follow its source when it is edited or deleted.
+//!
+//! Replies are remembered for a while, and kept in a json file when one is
+//! configured so a restart doesn't forget them.
+use super::json_state::StateFile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct TrackedReply {
+    pub channel: u64,
+    pub source: u64,
+    pub reply: u64,
+    /// Who posted the source message
+    pub author: u64,
+    /// Seconds since the epoch
+    pub created: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    replies: Vec<TrackedReply>,
+}
+
+#[derive(Debug)]
+pub(super) struct ReplyTracker {
+    file: Option<StateFile>,
+    window: Duration,
+    /// By source message
+    replies: Arc<Mutex<HashMap<u64, TrackedReply>>>,
+}
+
+impl ReplyTracker {
+    /// Only remember replies until the bot stops
+    pub(super) fn in_memory(window: Duration) -> ReplyTracker {
+        ReplyTracker {
+            file: None,
+            window,
+            replies: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Pick up the replies saved at `path`, if there are any
+    pub(super) fn load(path: PathBuf, window: Duration) -> anyhow::Result<ReplyTracker> {
+        let file = StateFile::new(path);
+        let state: State = file.load()?;
+        let tracker = ReplyTracker {
+            file: Some(file),
+            window,
+            replies: Arc::new(Mutex::new(
+                state.replies.into_iter().map(|r| (r.source, r)).collect(),
+            )),
+        };
+        tracker.prune(now_secs());
+        Ok(tracker)
+    }
+
+    pub(super) async fn track(&self, channel: u64, source: u64, reply: u64, author: u64) {
+        let now = now_secs();
+        self.replies.lock().unwrap().insert(
+            source,
+            TrackedReply {
+                channel,
+                source,
+                reply,
+                author,
+                created: now,
+            },
+        );
+        self.prune(now);
+        self.save().await;
+    }
+
+    pub(super) fn by_source(&self, source: u64) -> Option<TrackedReply> {
+        self.replies.lock().unwrap().get(&source).copied()
+    }
+
+    pub(super) fn by_reply(&self, reply: u64) -> Option<TrackedReply> {
+        self.replies
+            .lock()
+            .unwrap()
+            .values()
+            .find(|r| r.reply == reply)
+            .copied()
+    }
+
+    pub(super) async fn forget(&self, source: u64) {
+        if self.replies.lock().unwrap().remove(&source).is_some() {
+            self.save().await;
+        }
+    }
+
+    /// Drop the replies that are older than the window
+    fn prune(&self, now: u64) {
+        let oldest = now.saturating_sub(self.window.as_secs());
+        self.replies
+            .lock()
+            .unwrap()
+            .retain(|_, r| r.created >= oldest);
+    }
+
+    async fn save(&self) {
+        let file = match &self.file {
+            Some(f) => f,
+            None => return,
+        };
+        let replies = self.replies.clone();
+        let saved = file
+            .save(move || State {
+                replies: replies.lock().unwrap().values().copied().collect(),
+            })
+            .await;
+        if let Err(e) = saved {
+            log::warn!("could not save replies to {}: {}", file.path().display(), e);
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::json_state;
+
+    #[tokio::test]
+    async fn replies_survive_a_restart() {
+        let path = json_state::test_path("replies");
+        let tracker = ReplyTracker::load(path.clone(), DEFAULT_WINDOW).unwrap();
+        tracker.track(1, 10, 20, 99).await;
+        tracker.track(1, 11, 21, 99).await;
+        tracker.forget(11).await;
+
+        let reloaded = ReplyTracker::load(path.clone(), DEFAULT_WINDOW).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.by_source(10).map(|r| r.reply), Some(20));
+        assert_eq!(reloaded.by_reply(20).map(|r| r.source), Some(10));
+        assert_eq!(reloaded.by_source(11), None);
+    }
+
+    #[tokio::test]
+    async fn old_replies_are_dropped() {
+        let tracker = ReplyTracker::in_memory(Duration::from_secs(60));
+        tracker.track(1, 10, 20, 99).await;
+        tracker.prune(now_secs() + 120);
+        assert_eq!(tracker.by_source(10), None);
+    }
+}
@@ -0,0 +1,256 @@
+//! Shrinking videos that are too big to upload, with ffmpeg.
+//!
+//! A lower resolution of a v.redd.it video is tried first, since reddit has
+//! already done the work. Failing that the video is re-encoded with a
+//! bitrate that should land under the limit.
+use super::upload;
+use crate::media;
+use anyhow::Context;
+use reqwest::Url;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+pub const DEFAULT_JOBS: usize = 2;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The resolutions reddit encodes videos at, from the top
+const DASH_RENDITIONS: &[u32] = &[1080, 720, 480, 360, 240, 96];
+const AUDIO_BITRATE: u64 = 96_000;
+/// Leave room for the container, and for the encoder overshooting
+const SIZE_MARGIN: f64 = 0.9;
+/// Below this the video isn't worth watching
+const MIN_VIDEO_BITRATE: u64 = 100_000;
+/// What ffmpeg may open, since the url comes from a post: plain https, and
+/// containers that can't point it at other files the way playlists do
+const INPUT_LIMITS: &[&str] = &[
+    "-protocol_whitelist",
+    "https,tls,tcp",
+    "-format_whitelist",
+    "mov,mp4,m4a,3gp,3g2,mj2,matroska,webm,gif",
+];
+
+/// The options ffmpeg and ffprobe open `url` with, which go before it
+fn input_options(url: &str) -> Vec<String> {
+    let mut options: Vec<String> = INPUT_LIMITS.iter().map(|o| o.to_string()).collect();
+    if let Some(referer) = Url::parse(url).ok().as_ref().and_then(media::referer) {
+        options.push("-headers".to_string());
+        options.push(format!("Referer: {}\r\n", referer));
+    }
+    options
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeConfig {
+    /// How many ffmpeg jobs may run at once
+    pub jobs: usize,
+    /// How long a video may take to shrink before giving up on it
+    pub timeout: Duration,
+}
+
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        TranscodeConfig {
+            jobs: DEFAULT_JOBS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+pub struct Transcoder {
+    jobs: Semaphore,
+    timeout: Duration,
+}
+
+impl Transcoder {
+    pub fn new(config: TranscodeConfig) -> Transcoder {
+        Transcoder {
+            jobs: Semaphore::new(config.jobs.max(1)),
+            timeout: config.timeout,
+        }
+    }
+
+    /// A version of the video at `url` that is at most `cap` bytes, if one can be made
+    pub async fn shrink(&self, url: &str, cap: u64) -> anyhow::Result<Option<Vec<u8>>> {
+        for lower in lower_renditions(url) {
+            match upload::fetch_capped(&lower, cap).await {
+                Ok(Some(data)) => {
+                    log::info!("using {} in place of {}", lower, url);
+                    return Ok(Some(data));
+                }
+                Ok(None) => {}
+                // not every resolution exists for every video
+                Err(e) => log::debug!("no rendition at {}: {:#}", lower, e),
+            }
+        }
+
+        let _permit = self.jobs.acquire().await?;
+        match tokio::time::timeout(self.timeout, reencode(url, cap)).await {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("transcoding {} took longer than {:?}", url, self.timeout),
+        }
+    }
+}
+
+/// The smaller resolutions of a `v.redd.it/<id>/DASH_<n>.mp4` video, largest first
+fn lower_renditions(video: &str) -> Vec<String> {
+    let parse = || -> Option<(String, u32)> {
+        let url = url::Url::parse(video).ok()?;
+        if url.host_str() != Some("v.redd.it") {
+            return None;
+        }
+        let mut segments = url.path_segments()?;
+        let id = segments.next()?;
+        let height = segments
+            .next()?
+            .strip_prefix("DASH_")?
+            .strip_suffix(".mp4")?
+            .parse()
+            .ok()?;
+        Some((format!("https://v.redd.it/{}", id), height))
+    };
+    match parse() {
+        Some((base, height)) => DASH_RENDITIONS
+            .iter()
+            .filter(|&&h| h < height)
+            .map(|h| format!("{}/DASH_{}.mp4", base, h))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The video bitrate that fits `duration` seconds into `cap` bytes, alongside the audio
+fn video_bitrate(cap: u64, duration: f64) -> Option<u64> {
+    if duration <= 0.0 {
+        return None;
+    }
+    let total = (cap as f64 * 8.0 * SIZE_MARGIN / duration) as u64;
+    let video = total.checked_sub(AUDIO_BITRATE)?;
+    if video < MIN_VIDEO_BITRATE {
+        None
+    } else {
+        Some(video)
+    }
+}
+
+async fn reencode(url: &str, cap: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let duration = probe_duration(url).await?;
+    let bitrate = match video_bitrate(cap, duration) {
+        Some(b) => b,
+        None => {
+            log::info!(
+                "{} is too long ({}s) to fit in {} bytes",
+                url,
+                duration,
+                cap
+            );
+            return Ok(None);
+        }
+    };
+
+    // a fresh directory only we can write to, removed when it is dropped
+    let dir = tempfile::Builder::new()
+        .prefix("bananagrabber-")
+        .tempdir()?;
+    let out = dir.path().join("out.mp4");
+    log::info!("re-encoding {} at {}bps", url, bitrate);
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(input_options(url))
+        .args(["-i", url])
+        .args(["-c:v", "libx264", "-preset", "veryfast"])
+        .arg("-b:v")
+        .arg(bitrate.to_string())
+        .arg("-maxrate")
+        .arg(bitrate.to_string())
+        .arg("-bufsize")
+        .arg((bitrate * 2).to_string())
+        .arg("-c:a")
+        .arg("aac")
+        .arg("-b:a")
+        .arg(AUDIO_BITRATE.to_string())
+        .args(["-movflags", "+faststart"])
+        .arg(&out)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .context("run ffmpeg")?;
+    if !status.success() {
+        anyhow::bail!("ffmpeg failed with {}", status);
+    }
+
+    let data = std::fs::read(&out)?;
+    if data.len() as u64 > cap {
+        log::info!(
+            "{} re-encoded to {} bytes, still over {}",
+            url,
+            data.len(),
+            cap
+        );
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+/// How long the video is, in seconds
+async fn probe_duration(url: &str) -> anyhow::Result<f64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .args(input_options(url))
+        .arg(url)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("run ffprobe")?;
+    if !output.status.success() {
+        anyhow::bail!("ffprobe failed with {}", output.status);
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.trim()
+        .parse()
+        .with_context(|| format!("ffprobe duration {:?}", text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renditions_below_the_original() {
+        assert_eq!(
+            lower_renditions("https://v.redd.it/u23a45f7pcd81/DASH_480.mp4?source=fallback"),
+            vec![
+                "https://v.redd.it/u23a45f7pcd81/DASH_360.mp4",
+                "https://v.redd.it/u23a45f7pcd81/DASH_240.mp4",
+                "https://v.redd.it/u23a45f7pcd81/DASH_96.mp4",
+            ]
+        );
+        assert!(lower_renditions("https://i.imgur.com/abc.mp4").is_empty());
+    }
+
+    #[test]
+    fn bitrate_fits_the_cap() {
+        // 25MB over 100 seconds
+        let bitrate = video_bitrate(25 * 1024 * 1024, 100.0).unwrap();
+        assert_eq!(bitrate, 1_791_436);
+        assert!((bitrate + AUDIO_BITRATE) as f64 * 100.0 / 8.0 < 25.0 * 1024.0 * 1024.0);
+        // too long to be watchable
+        assert_eq!(video_bitrate(8 * 1024 * 1024, 3600.0), None);
+        assert_eq!(video_bitrate(8 * 1024 * 1024, 0.0), None);
+    }
+
+    #[test]
+    fn reddit_videos_are_opened_with_a_referer() {
+        let options = input_options("https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?source=fallback");
+        assert_eq!(&options[..INPUT_LIMITS.len()], INPUT_LIMITS);
+        assert_eq!(
+            &options[INPUT_LIMITS.len()..],
+            ["-headers", "Referer: https://www.reddit.com/\r\n"]
+        );
+        assert_eq!(input_options("https://i.imgur.com/abc.mp4"), INPUT_LIMITS);
+    }
+}
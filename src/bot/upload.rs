@@ -0,0 +1,260 @@
+//! Uploading media to discord as attachments, for links that discord
+//! won't embed on its own (hosts that block its crawler, or want a referer).
+//!
+//! Media links come from posts anyone can make, so only https hosts that
+//! resolve to public addresses are fetched from, and only images and videos
+//! are uploaded.
+use crate::media;
+use anyhow::Context;
+use reqwest::header::{CONTENT_TYPE, LOCATION, REFERER};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serenity::model::guild::PremiumTier;
+use std::net::{IpAddr, SocketAddr};
+
+const MB: u64 = 1024 * 1024;
+/// What any server, or a DM, can upload
+pub const DEFAULT_UPLOAD_LIMIT: u64 = 25 * MB;
+const MAX_REDIRECTS: usize = 5;
+/// Names that only mean something on the local network
+const LOCAL_SUFFIXES: &[&str] = &[".localhost", ".local", ".internal"];
+
+/// Whether `url` is https on a public domain name, rather than an ip address
+/// or a name that only resolves on the local network
+pub fn is_public_https(url: &Url) -> bool {
+    if url.scheme() != "https" {
+        return false;
+    }
+    match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain.contains('.') && !LOCAL_SUFFIXES.iter().any(|s| domain.ends_with(s))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `ip` is on the internet, rather than private, loopback, link-local
+/// or otherwise special
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // carrier-grade nat
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // reserved, including addresses with an ipv4 one inside
+                || first == 0
+                // unique local
+                || (first & 0xfe00) == 0xfc00
+                // link-local
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolve the host of a public https `url`, refusing it if any of its
+/// addresses isn't public, and return the address to connect to
+pub async fn resolve_public(url: &Url) -> anyhow::Result<SocketAddr> {
+    if !is_public_https(url) {
+        anyhow::bail!("{} is not on a public https host", url);
+    }
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("resolve {}", host))?
+        .collect();
+    if let Some(addr) = addrs.iter().find(|a| !is_public_ip(a.ip())) {
+        anyhow::bail!("{} resolves to {}, which isn't public", host, addr.ip());
+    }
+    addrs
+        .first()
+        .copied()
+        .with_context(|| format!("{} has no addresses", host))
+}
+
+/// The most a message can carry in attachments, which goes up with the
+/// server's boost tier
+pub fn upload_limit(tier: PremiumTier) -> u64 {
+    match tier {
+        PremiumTier::Tier2 => 50 * MB,
+        PremiumTier::Tier3 => 100 * MB,
+        _ => DEFAULT_UPLOAD_LIMIT,
+    }
+}
+
+/// Download `url`, giving up with `None` as soon as it is clear that it is
+/// bigger than `cap` bytes
+///
+/// Redirects are followed by hand, so that every host along the way is
+/// checked, and connected to at the address that was checked.
+pub async fn fetch_capped(url: &str, cap: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut url = Url::parse(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let addr = resolve_public(&url).await?;
+        let client = crate::transport::client_builder()
+            .redirect(Policy::none())
+            .resolve(url.host_str().unwrap_or_default(), addr)
+            .build()?;
+        let mut req = client.get(url.clone());
+        if let Some(referer) = media::referer(&url) {
+            req = req.header(REFERER, referer);
+        }
+        let resp = req.send().await?;
+        if resp.status().is_redirection() {
+            let location = resp
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .with_context(|| format!("{} redirected without a location", url))?;
+            url = url.join(location)?;
+            continue;
+        }
+        return read_capped(resp, cap).await;
+    }
+    anyhow::bail!("too many redirects")
+}
+
+/// The body of an image or video response, or `None` if it is bigger than
+/// `cap` bytes
+async fn read_capped(resp: reqwest::Response, cap: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let url = resp.url().clone();
+    let mut resp = resp
+        .error_for_status()
+        .with_context(|| format!("download {}", url))?;
+    let mime = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !(mime.starts_with("image/") || mime.starts_with("video/")) {
+        anyhow::bail!("{} is {:?}, not an image or video", url, mime);
+    }
+    if resp.content_length().is_some_and(|len| len > cap) {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if (data.len() + chunk.len()) as u64 > cap {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer one request with `head` and then `body`, returning its url
+    fn serve_once(head: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://{}/video.mp4", addr)
+    }
+
+    async fn get(url: &str) -> reqwest::Response {
+        reqwest::Client::new().get(url).send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_under_cap() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\ncontent-type: video/mp4\r\ncontent-length: 100\r\nconnection: close\r\n\r\n",
+            vec![7; 100],
+        );
+        let data = read_capped(get(&url).await, 100).await.unwrap();
+        assert_eq!(data, Some(vec![7; 100]));
+    }
+
+    #[tokio::test]
+    async fn read_over_cap_without_length() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\ncontent-type: video/mp4\r\nconnection: close\r\n\r\n",
+            vec![7; 100],
+        );
+        let data = read_capped(get(&url).await, 50).await.unwrap();
+        assert_eq!(data, None);
+    }
+
+    #[tokio::test]
+    async fn only_media_is_read() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\n",
+            b"AccessKeyId".to_vec(),
+        );
+        assert!(read_capped(get(&url).await, 100).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn local_hosts_are_not_fetched() {
+        // refused before anything is resolved or connected to
+        let url = serve_once("HTTP/1.1 200 OK\r\n\r\n", Vec::new());
+        assert!(fetch_capped(&url, 100).await.is_err());
+        assert!(fetch_capped("https://localhost/x.png", 100).await.is_err());
+    }
+
+    #[test]
+    fn public_addresses() {
+        let public = |ip: &str| is_public_ip(ip.parse().unwrap());
+        assert!(public("151.101.1.140"));
+        assert!(public("2a04:4e42::396"));
+        assert!(!public("127.0.0.1"));
+        assert!(!public("10.1.2.3"));
+        assert!(!public("172.16.0.1"));
+        assert!(!public("192.168.1.1"));
+        assert!(!public("169.254.169.254"));
+        assert!(!public("100.64.0.1"));
+        assert!(!public("0.0.0.0"));
+        assert!(!public("::1"));
+        assert!(!public("fd00::1"));
+        assert!(!public("fe80::1"));
+        assert!(!public("::ffff:127.0.0.1"));
+        assert!(!public("::127.0.0.1"));
+    }
+
+    #[test]
+    fn public_https_hosts() {
+        let public = |u: &str| is_public_https(&Url::parse(u).unwrap());
+        assert!(public("https://i.redd.it/kaopcso5hqw61.png"));
+        assert!(!public("http://i.redd.it/kaopcso5hqw61.png"));
+        assert!(!public("https://169.254.169.254/latest/meta-data/x.png"));
+        assert!(!public("https://[::1]/x.png"));
+        assert!(!public("https://localhost/x.png"));
+        assert!(!public("https://instance-data.ec2.internal/x.png"));
+    }
+
+    #[test]
+    fn limit_by_tier() {
+        assert_eq!(upload_limit(PremiumTier::Tier0), 25 * MB);
+        assert_eq!(upload_limit(PremiumTier::Tier1), 25 * MB);
+        assert_eq!(upload_limit(PremiumTier::Tier3), 100 * MB);
+    }
+}
@@ -0,0 +1,107 @@
+//! Caching of extraction results, so repeated links don't go back to reddit.
+use crate::media_extraction::MediaOutcome;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "sqlite-cache")]
+mod sqlite;
+
+#[cfg(feature = "sqlite-cache")]
+pub use sqlite::SqliteCache;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_CAPACITY: usize = 1024;
+
+pub trait Cache: Send + Sync {
+    fn get(&self, url: &str) -> Option<MediaOutcome>;
+    fn put(&self, url: &str, outcome: &MediaOutcome);
+
+    /// Make sure everything put so far is written out, before the process exits.
+    /// Caches that write as they go have nothing to do.
+    fn flush(&self) {}
+}
+
+/// Never remembers anything
+pub struct NoCache;
+
+impl Cache for NoCache {
+    fn get(&self, _url: &str) -> Option<MediaOutcome> {
+        None
+    }
+
+    fn put(&self, _url: &str, _outcome: &MediaOutcome) {}
+}
+
+/// Keeps results in memory for a while, dropping the oldest when full
+pub struct MemoryCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, (Instant, MediaOutcome)>>,
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        MemoryCache::new(DEFAULT_TTL, DEFAULT_CAPACITY)
+    }
+}
+
+impl MemoryCache {
+    pub fn new(ttl: Duration, capacity: usize) -> MemoryCache {
+        MemoryCache {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, url: &str) -> Option<MediaOutcome> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(url) {
+            Some((at, outcome)) if at.elapsed() < self.ttl => Some(outcome.clone()),
+            Some(_) => {
+                entries.remove(url);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&self, url: &str, outcome: &MediaOutcome) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(url) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                entries.remove(&k);
+            }
+        }
+        entries.insert(url.to_string(), (Instant::now(), outcome.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_cache_evicts_oldest() {
+        let cache = MemoryCache::new(DEFAULT_TTL, 2);
+        cache.put("a", &MediaOutcome::NotFound);
+        cache.put("b", &MediaOutcome::NotFound);
+        cache.put("c", &MediaOutcome::NotFound);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c"), Some(MediaOutcome::NotFound));
+    }
+
+    #[test]
+    fn memory_cache_expires() {
+        let cache = MemoryCache::new(Duration::from_secs(0), 2);
+        cache.put("a", &MediaOutcome::NotFound);
+        assert_eq!(cache.get("a"), None);
+    }
+}
@@ -0,0 +1,93 @@
+use super::Cache;
+use crate::media_extraction::MediaOutcome;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Keeps results in a sqlite database, so they survive restarts
+pub struct SqliteCache {
+    ttl: Duration,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteCache {
+    pub fn open<P: AsRef<Path>>(path: P, ttl: Duration) -> anyhow::Result<SqliteCache> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS extractions (
+                url TEXT PRIMARY KEY,
+                outcome TEXT NOT NULL,
+                stored_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(SqliteCache {
+            ttl,
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn lookup(&self, url: &str) -> anyhow::Result<Option<MediaOutcome>> {
+        let oldest = now_secs().saturating_sub(self.ttl.as_secs()) as i64;
+        let conn = self.conn.lock().unwrap();
+        let outcome: Option<String> = conn
+            .query_row(
+                "SELECT outcome FROM extractions WHERE url = ?1 AND stored_at >= ?2",
+                params![url, oldest],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match outcome {
+            Some(o) => Ok(Some(serde_json::from_str(&o)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store(&self, url: &str, outcome: &MediaOutcome) -> anyhow::Result<()> {
+        let outcome = serde_json::to_string(outcome)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO extractions (url, outcome, stored_at) VALUES (?1, ?2, ?3)",
+            params![url, outcome, now_secs() as i64],
+        )?;
+        Ok(())
+    }
+}
+
+impl Cache for SqliteCache {
+    fn get(&self, url: &str) -> Option<MediaOutcome> {
+        self.lookup(url).unwrap_or_else(|e| {
+            log::warn!("could not read cache: {}", e);
+            None
+        })
+    }
+
+    fn put(&self, url: &str, outcome: &MediaOutcome) {
+        if let Err(e) = self.store(url, outcome) {
+            log::warn!("could not write cache: {}", e);
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::Media;
+
+    #[test]
+    fn sqlite_cache_round_trip() {
+        let cache = SqliteCache::open(":memory:", Duration::from_secs(60)).unwrap();
+        let outcome = MediaOutcome::Found(vec![Media::new("https://i.redd.it/kaopcso5hqw61.jpg")]);
+        cache.put("https://redd.it/n39zq5", &outcome);
+        assert_eq!(cache.get("https://redd.it/n39zq5"), Some(outcome));
+        assert_eq!(cache.get("https://redd.it/other"), None);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::argparse;
+use crate::fixture;
+use anyhow::Context;
+use bananagrabber::record;
+use bananagrabber::reddit;
+use bananagrabber::{Extraction, Extractor, MediaOutcome};
+use futures::StreamExt;
+use std::io::Read;
+
+/// Scan every fixture in the directory and compare it to its expectation.
+///
+/// With `--update` the expectations are rewritten to match instead.
+pub fn check_saved_responses(opts: &argparse::Test) -> anyhow::Result<()> {
+    let names = fixture::names(&opts.file)?;
+    let mut failed = 0;
+    let mut passed = 0;
+    let mut skipped = 0;
+
+    for name in &names {
+        let (fixture_path, expected_path) = fixture::paths(&opts.file, name);
+        log::debug!("begin {:?}", fixture_path);
+        let mut expectation = if expected_path.exists() {
+            fixture::Expectation::load(&expected_path)
+                .with_context(|| format!("load {}", expected_path.display()))?
+        } else {
+            fixture::Expectation::default()
+        };
+
+        if let Some(reason) = &expectation.skip {
+            println!("SKIP  {:<24} {}", name, reason);
+            skipped += 1;
+            continue;
+        }
+
+        let actual = fixture::Scan::run_file(&fixture_path, expectation.url.as_deref());
+        if expectation.result.as_ref() == Some(&actual) {
+            println!("PASS  {}", name);
+            passed += 1;
+        } else if opts.update {
+            println!("BLESS {}", name);
+            expectation.result = Some(actual);
+            fixture::write_json(&expected_path, &expectation)?;
+            passed += 1;
+        } else {
+            println!("FAIL  {}", name);
+            match &expectation.result {
+                Some(expected) => print!(
+                    "{}",
+                    fixture::diff(
+                        &serde_json::to_string_pretty(expected)?,
+                        &serde_json::to_string_pretty(&actual)?
+                    )
+                ),
+                None => println!("      no {} for this fixture", expected_path.display()),
+            }
+            failed += 1;
+        }
+    }
+
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        passed, failed, skipped
+    );
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} fixtures did not match, re-bless with --update",
+            failed,
+            names.len()
+        );
+    }
+    Ok(())
+}
+
+pub async fn fetch_url(
+    opts: &argparse::ExtractMediaUrl,
+    extractor: &Extractor,
+) -> anyhow::Result<()> {
+    if opts.format == argparse::OutputFormat::Json {
+        let extraction = extractor.extract_detailed(&opts.url).await;
+        print_record(&opts.url, &extraction)?;
+        return extraction.outcome.map(|_| ());
+    }
+    match extractor.extract(&opts.url).await? {
+        MediaOutcome::Found(media) => {
+            for m in media {
+                println!("{}", m.url);
+            }
+        }
+        MediaOutcome::Unavailable(u) => log::warn!("post is {}", u),
+        MediaOutcome::Text(q) => println!("{}\n\n{}", q.title, q.text),
+        MediaOutcome::NotFound => log::warn!("could not find media"),
+    }
+    Ok(())
+}
+
+/// Extract the links in the input concurrently, printing the results in input order
+pub async fn fetch_batch(
+    opts: &argparse::ExtractBatch,
+    extractor: &Extractor,
+) -> anyhow::Result<()> {
+    let input = if opts.input == "-" {
+        let mut s = String::new();
+        std::io::stdin().read_to_string(&mut s)?;
+        s
+    } else {
+        std::fs::read_to_string(&opts.input).with_context(|| format!("read {}", opts.input))?
+    };
+    let urls = batch_urls(&input);
+    log::info!("extracting {} links", urls.len());
+
+    let mut results = futures::stream::iter(urls)
+        .map(|url| async move {
+            let extraction = extractor.extract_detailed(url).await;
+            (url, extraction)
+        })
+        .buffered(opts.concurrency.max(1));
+
+    let mut failed = 0;
+    while let Some((url, extraction)) = results.next().await {
+        if extraction.outcome.is_err() {
+            failed += 1;
+        }
+        match opts.format {
+            argparse::OutputFormat::Json => print_record(url, &extraction)?,
+            argparse::OutputFormat::Text => println!("{}\t{}", url, text_line(&extraction)),
+        }
+    }
+    if failed > 0 {
+        log::warn!("{} links could not be extracted", failed);
+    }
+    Ok(())
+}
+
+fn print_record(url: &str, extraction: &Extraction) -> anyhow::Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string(&record::Record::new(url, extraction))?
+    );
+    Ok(())
+}
+
+/// A one line summary of the extraction, for text batch output
+fn text_line(extraction: &Extraction) -> String {
+    match &extraction.outcome {
+        Ok(MediaOutcome::Found(media)) => media
+            .iter()
+            .map(|m| m.url.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        Ok(MediaOutcome::Unavailable(u)) => format!("unavailable: post is {}", u),
+        Ok(MediaOutcome::Text(q)) => format!("text: {}", q.title),
+        Ok(MediaOutcome::NotFound) => "not found".to_string(),
+        Err(e) => format!("error: {:#}", e),
+    }
+}
+
+/// A line that is a url is taken as is, otherwise the reddit links are picked out of it
+fn batch_urls(input: &str) -> Vec<&str> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .flat_map(|l| {
+            if !l.contains(char::is_whitespace) && url::Url::parse(l).is_ok() {
+                vec![l]
+            } else {
+                reddit::reddit_links_in_text(l)
+            }
+        })
+        .collect()
+}
+
+pub async fn save_url(
+    opts: &argparse::FetchTestCase,
+    client: &reddit::RedditClient,
+) -> anyhow::Result<()> {
+    let mut resp = client.get_url_as::<serde_json::Value>(&opts.url).await?;
+    if opts.minimize {
+        let typed: reddit::ApiResponse =
+            serde_json::from_value(resp).context("deserialize api response")?;
+        let comment_id = reddit::comment_id_from_url(&opts.url);
+        resp = serde_json::to_value(typed.minimize(comment_id.as_deref()))?;
+    }
+
+    let name = match &opts.name {
+        Some(n) => n,
+        None => {
+            println!("{}", serde_json::to_string_pretty(&resp)?);
+            return Ok(());
+        }
+    };
+
+    let (fixture_path, expected_path) = fixture::paths(&opts.dir, name);
+    if !opts.force {
+        for path in [&fixture_path, &expected_path] {
+            if path.exists() {
+                anyhow::bail!(
+                    "{} already exists, use --force to overwrite",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    let expectation = fixture::Expectation {
+        url: Some(opts.url.clone()),
+        skip: None,
+        result: Some(fixture::Scan::run(&resp, &opts.url)),
+    };
+    fixture::write_json(&fixture_path, &resp)?;
+    fixture::write_json(&expected_path, &expectation)?;
+    log::info!("saved {}", fixture_path.display());
+    println!("{:#?}", fixture::Scan::run(&resp, &opts.url));
+    Ok(())
+}
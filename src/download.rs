@@ -0,0 +1,267 @@
+//! Save the media of a reddit link to disk.
+//!
+//! Files are named `<subreddit>_<post id>_<title slug>`, with a sidecar
+//! `.json` describing the post. Downloads go to a `.part` file first,
+//! which is resumed with a range request if it is still there next time.
+//!
+//! The extractor already picks the best rendition it can find (reddit's
+//! fallback video is its highest quality, mp4 is taken over gif), v.redd.it
+//! video has its audio merged back in when ffmpeg is installed.
+use crate::argparse;
+use anyhow::Context;
+use bananagrabber::media;
+use bananagrabber::record::MediaRecord;
+use bananagrabber::reddit::PostSummary;
+use bananagrabber::{Extractor, MediaOutcome};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const SLUG_LEN: usize = 50;
+
+/// v.redd.it keeps the audio in its own file, the name has changed over the years
+const VREDDIT_AUDIO: &[&str] = &[
+    "DASH_AUDIO_128.mp4",
+    "DASH_AUDIO_64.mp4",
+    "DASH_audio.mp4",
+    "audio",
+];
+
+#[derive(Debug, Serialize)]
+struct Sidecar<'a> {
+    input: &'a str,
+    post: Option<&'a PostSummary>,
+    media: Vec<MediaRecord>,
+    files: Vec<String>,
+}
+
+pub async fn download(opts: &argparse::Download, extractor: &Extractor) -> anyhow::Result<()> {
+    let extraction = extractor.extract_detailed(&opts.url).await;
+    let media = match extraction.outcome? {
+        MediaOutcome::Found(m) => m,
+        MediaOutcome::Unavailable(u) => anyhow::bail!("post is {}", u),
+        MediaOutcome::Text(_) => anyhow::bail!("text post, there is nothing to download"),
+        MediaOutcome::NotFound => anyhow::bail!("could not find media"),
+    };
+
+    let dir = Path::new(&opts.dir);
+    std::fs::create_dir_all(dir)?;
+    let base = base_name(extraction.post.as_ref(), &opts.url);
+    let client = bananagrabber::transport::client_builder().build()?;
+
+    let mut files = Vec::new();
+    for (idx, m) in media.iter().enumerate() {
+        let ext = match m.extension() {
+            Some(e) => e,
+            None => {
+                log::warn!("{} is not a media file, skipping it", m.url);
+                continue;
+            }
+        };
+        let name = if media.len() == 1 {
+            format!("{}.{}", base, ext)
+        } else {
+            format!("{}_{}.{}", base, idx + 1, ext)
+        };
+        let path = dir.join(&name);
+        if path.exists() {
+            log::info!("{} already downloaded", path.display());
+        } else {
+            fetch_file(&client, &m.url, &path).await?;
+            if !opts.no_audio {
+                if let Some(audio_urls) = vreddit_audio_urls(&m.url) {
+                    add_vreddit_audio(&client, &audio_urls, &path).await;
+                }
+            }
+        }
+        println!("{}", path.display());
+        files.push(name);
+    }
+    if files.is_empty() {
+        anyhow::bail!("none of the media could be downloaded");
+    }
+
+    let sidecar = Sidecar {
+        input: &opts.url,
+        post: extraction.post.as_ref(),
+        media: media.iter().map(MediaRecord::from).collect(),
+        files,
+    };
+    let sidecar_path = dir.join(format!("{}.json", base));
+    std::fs::write(&sidecar_path, serde_json::to_string_pretty(&sidecar)?)?;
+    Ok(())
+}
+
+/// Download to `<path>.part`, picking up where an earlier attempt left off,
+/// and move it into place once it is complete
+async fn fetch_file(client: &reqwest::Client, url: &str, path: &Path) -> anyhow::Result<()> {
+    let part = part_path(path);
+    let have = std::fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+
+    let mut req = client.get(url);
+    if let Some(referer) = reqwest::Url::parse(url)
+        .ok()
+        .as_ref()
+        .and_then(media::referer)
+    {
+        req = req.header(reqwest::header::REFERER, referer);
+    }
+    if have > 0 {
+        log::info!("resuming {} from {} bytes", path.display(), have);
+        req = req.header(reqwest::header::RANGE, format!("bytes={}-", have));
+    }
+    let mut resp = req.send().await?;
+    if resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // the part file is already complete
+        std::fs::rename(&part, path)?;
+        return Ok(());
+    }
+    let resp_status = resp.status();
+    resp = resp
+        .error_for_status()
+        .with_context(|| format!("download {}", url))?;
+
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resp_status == reqwest::StatusCode::PARTIAL_CONTENT)
+        .truncate(resp_status != reqwest::StatusCode::PARTIAL_CONTENT)
+        .open(&part)?;
+    while let Some(chunk) = resp.chunk().await? {
+        f.write_all(&chunk)?;
+    }
+    f.sync_all()?;
+    std::fs::rename(&part, path)?;
+    Ok(())
+}
+
+/// Merge the audio track into the video with ffmpeg, keeping the silent
+/// video if there is no audio or ffmpeg is not around
+async fn add_vreddit_audio(client: &reqwest::Client, audio_urls: &[String], video: &Path) {
+    let audio = video.with_extension("audio.mp4");
+    let mut found = false;
+    for url in audio_urls {
+        match fetch_file(client, url, &audio).await {
+            Ok(()) => {
+                found = true;
+                break;
+            }
+            Err(e) => log::debug!("no audio at {}: {:#}", url, e),
+        }
+    }
+    if !found {
+        log::info!("{} has no audio", video.display());
+        let _ = std::fs::remove_file(part_path(&audio));
+        return;
+    }
+
+    let merged = video.with_extension("merged.mp4");
+    let status = tokio::process::Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(video)
+        .arg("-i")
+        .arg(&audio)
+        .args(["-c", "copy"])
+        .arg(&merged)
+        .status()
+        .await;
+    match status {
+        Ok(s) if s.success() => {
+            if let Err(e) = std::fs::rename(&merged, video) {
+                log::warn!("could not replace {}: {}", video.display(), e);
+            }
+        }
+        Ok(s) => log::warn!("ffmpeg failed ({}), keeping the video without audio", s),
+        Err(e) => log::warn!(
+            "could not run ffmpeg ({}), keeping the video without audio",
+            e
+        ),
+    }
+    let _ = std::fs::remove_file(&audio);
+    let _ = std::fs::remove_file(&merged);
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".part");
+    PathBuf::from(p)
+}
+
+/// `<subreddit>_<post id>_<title slug>`, falling back to the link when the post is unknown
+fn base_name(post: Option<&PostSummary>, url: &str) -> String {
+    match post {
+        Some(p) => {
+            let slug = slug(&p.title);
+            if slug.is_empty() {
+                format!("{}_{}", p.subreddit, p.id)
+            } else {
+                format!("{}_{}_{}", p.subreddit, p.id, slug)
+            }
+        }
+        None => slug(url),
+    }
+}
+
+fn slug(text: &str) -> String {
+    let mut s = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            s.push(c.to_ascii_lowercase());
+        } else if !s.ends_with('-') && !s.is_empty() {
+            s.push('-');
+        }
+        if s.len() >= SLUG_LEN {
+            break;
+        }
+    }
+    s.trim_end_matches('-').to_string()
+}
+
+/// The places the audio of a `v.redd.it/<id>/DASH_<n>.mp4` video might be
+fn vreddit_audio_urls(video: &str) -> Option<Vec<String>> {
+    let url = url::Url::parse(video).ok()?;
+    if url.host_str() != Some("v.redd.it") {
+        return None;
+    }
+    let id = url.path_segments()?.next()?;
+    Some(
+        VREDDIT_AUDIO
+            .iter()
+            .map(|f| format!("https://v.redd.it/{}/{}", id, f))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slug_of_title() {
+        assert_eq!(
+            slug("The edge-on galaxy NGC 5866 captured by Hubble."),
+            "the-edge-on-galaxy-ngc-5866-captured-by-hubble"
+        );
+    }
+
+    #[test]
+    fn base_name_of_post() {
+        let post = PostSummary {
+            id: "saj6w9".to_string(),
+            subreddit: "SpaceGifs".to_string(),
+            title: "Galaxy!".to_string(),
+            nsfw: false,
+        };
+        assert_eq!(base_name(Some(&post), ""), "SpaceGifs_saj6w9_galaxy");
+    }
+
+    #[test]
+    fn audio_for_vreddit() {
+        let urls = vreddit_audio_urls("https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?").unwrap();
+        assert_eq!(
+            urls[0],
+            "https://v.redd.it/u23a45f7pcd81/DASH_AUDIO_128.mp4"
+        );
+        assert!(vreddit_audio_urls("https://i.redd.it/kaopcso5hqw61.jpg").is_none());
+    }
+}
@@ -0,0 +1,149 @@
+//! Saved reddit responses in `sample_responses`, along with what we
+//! expect to extract from them.
+use bananagrabber::media_extraction::extract_media_from_respsonse;
+use bananagrabber::reddit::{self, ApiResponse, PostMediaSource};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const EXPECTED_SUFFIX: &str = ".expected.json";
+
+/// Written next to a fixture as `<name>.expected.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Expectation {
+    /// The link the fixture was fetched from, it decides which comment is targeted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Why the fixture is not checked, e.g. it is an error body rather than a post
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Scan>,
+}
+
+impl Expectation {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Expectation> {
+        let f = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+}
+
+/// The outcome of scanning a response, without touching the network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scan {
+    Source(Option<PostMediaSource>),
+    Error(String),
+}
+
+impl Scan {
+    /// Scan the fixture file, a file that can't be read is an error result
+    pub fn run_file<P: AsRef<Path>>(path: P, url: Option<&str>) -> Scan {
+        let json = std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| Ok(serde_json::from_str::<serde_json::Value>(&s)?));
+        match json {
+            Ok(j) => Scan::run(&j, url.unwrap_or_default()),
+            Err(e) => Scan::Error(format!("read fixture: {}", e)),
+        }
+    }
+
+    pub fn run(json: &serde_json::Value, url: &str) -> Scan {
+        let resp = match serde_json::from_value::<ApiResponse>(json.clone()) {
+            Ok(r) => r,
+            Err(e) => return Scan::Error(format!("deserialize api response: {}", e)),
+        };
+        let comment_id = reddit::comment_id_from_url(url);
+        match extract_media_from_respsonse(&resp, comment_id.as_deref()) {
+            Ok(source) => Scan::Source(source),
+            Err(e) => Scan::Error(e.to_string()),
+        }
+    }
+}
+
+/// The fixture names in a directory, skipping expectations and subdirectories
+pub fn names<P: AsRef<Path>>(dir: P) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.ends_with(EXPECTED_SUFFIX) {
+            continue;
+        }
+        if let Some(name) = file_name.strip_suffix(".json") {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// The fixture and expectation paths for a fixture name
+pub fn paths<P: AsRef<Path>>(dir: P, name: &str) -> (PathBuf, PathBuf) {
+    let dir = dir.as_ref();
+    (
+        dir.join(format!("{}.json", name)),
+        dir.join(format!("{}{}", name, EXPECTED_SUFFIX)),
+    )
+}
+
+pub fn write_json<P: AsRef<Path>, T: Serialize>(path: P, value: &T) -> anyhow::Result<()> {
+    let mut s = serde_json::to_string_pretty(value)?;
+    s.push('\n');
+    std::fs::write(path, s)?;
+    Ok(())
+}
+
+/// A line diff of `expected` against `actual`, `-` lines are only
+/// in `expected` and `+` lines only in `actual`
+pub fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // longest common subsequence, lcs[i][j] is for a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_marks_changed_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nx\nc"), "  a\n- b\n+ x\n  c\n");
+    }
+
+    #[test]
+    fn diff_of_equal_text() {
+        assert_eq!(diff("a\nb", "a\nb"), "  a\n  b\n");
+    }
+}
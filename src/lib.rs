@@ -0,0 +1,33 @@
+//! Extract the media out of reddit links.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let extractor = bananagrabber::Extractor::default();
+//! let outcome = extractor
+//!     .extract("https://www.reddit.com/r/gifs/comments/9zgyim/")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+// reddit and every media host are https only, so this would build fine and
+// then fail every request
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("enable a tls implementation, the `native-tls` or `rustls` feature");
+
+#[cfg(feature = "discord")]
+pub mod bot;
+pub mod cache;
+pub mod media;
+pub mod media_extraction;
+pub mod metrics;
+pub mod rate_limit;
+pub mod record;
+pub mod reddit;
+pub mod resolvers;
+#[cfg(feature = "http-server")]
+pub mod server;
+pub mod transport;
+
+pub use media::Media;
+pub use media_extraction::{Extraction, Extractor, MediaOutcome};
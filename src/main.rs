@@ -1,10 +1,14 @@
 use argparse::CliOpts;
+use bananagrabber::transport::{
+    HttpTransport, RecordingTransport, ReplayTransport, ReqwestTransport,
+};
+use bananagrabber::Extractor;
+use std::sync::Arc;
 
 mod argparse;
-mod bot;
-mod media;
-mod media_extraction;
-mod reddit;
+mod cli;
+mod download;
+mod fixture;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -24,13 +28,106 @@ async fn main() -> anyhow::Result<()> {
 
 async fn run(args: &CliOpts) -> anyhow::Result<()> {
     match &args.subcmd {
-        argparse::SubCommand::ExtractMediaUrl(opts) => media_extraction::fetch_url(opts).await,
-        argparse::SubCommand::FetchTestCase(opts) => media_extraction::save_url(opts).await,
-        argparse::SubCommand::Test(opts) => media_extraction::check_saved_responses(opts),
-        argparse::SubCommand::Bot(opts) => bot::bot_start().await,
+        argparse::SubCommand::ExtractMediaUrl(opts) => {
+            cli::fetch_url(opts, &build_extractor(args)?).await
+        }
+        argparse::SubCommand::ExtractBatch(opts) => {
+            cli::fetch_batch(opts, &build_extractor(args)?).await
+        }
+        argparse::SubCommand::Download(opts) => {
+            download::download(opts, &build_extractor(args)?).await
+        }
+        argparse::SubCommand::FetchTestCase(opts) => {
+            cli::save_url(opts, build_extractor(args)?.client()).await
+        }
+        argparse::SubCommand::Test(opts) => cli::check_saved_responses(opts),
+        #[cfg(feature = "http-server")]
+        argparse::SubCommand::Serve(opts) => serve(opts, build_extractor(args)?).await,
+        #[cfg(feature = "discord")]
+        argparse::SubCommand::Bot(opts) => {
+            bananagrabber::bot::bot_start(build_extractor(args)?, bot_config(opts)).await
+        }
     }
 }
 
+#[cfg(feature = "http-server")]
+async fn serve(opts: &argparse::ServeSettings, extractor: Extractor) -> anyhow::Result<()> {
+    use bananagrabber::server;
+    let mut auth = server::Auth {
+        api_keys: opts.api_key.clone(),
+        basic: opts.basic_auth.clone(),
+    };
+    if let Ok(keys) = std::env::var("BANANAGRABBER_API_KEYS") {
+        auth.api_keys.extend(
+            keys.split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(String::from),
+        );
+    }
+    if auth.basic.is_none() {
+        auth.basic = std::env::var("BANANAGRABBER_BASIC_AUTH").ok();
+    }
+    let config = server::ServerConfig {
+        addr: opts.addr,
+        timeout: std::time::Duration::from_secs(opts.timeout),
+        auth,
+    };
+    server::serve(extractor, config).await
+}
+
+#[cfg(feature = "discord")]
+fn bot_config(opts: &argparse::BotSettings) -> bananagrabber::bot::BotConfig {
+    bananagrabber::bot::BotConfig {
+        #[cfg(feature = "metrics")]
+        health_addr: opts.health_addr,
+        upload_media: opts.upload_media,
+        #[cfg(feature = "ffmpeg")]
+        transcode: opts.transcode.then(|| bananagrabber::bot::TranscodeConfig {
+            jobs: opts.transcode_jobs,
+            timeout: std::time::Duration::from_secs(opts.transcode_timeout),
+        }),
+        shutdown_deadline: std::time::Duration::from_secs(opts.shutdown_deadline),
+        #[cfg(feature = "metrics")]
+        metrics_file: opts.metrics_file.clone(),
+        sharding: opts.shards,
+        auto_detect: opts.auto_detect,
+        reply_state: opts.reply_state.clone(),
+        reply_window: std::time::Duration::from_secs(opts.reply_window * 60 * 60),
+        quotas: bananagrabber::bot::Quotas {
+            user: opts.user_quota,
+            channel: opts.channel_quota,
+            guild: opts.guild_quota,
+        },
+        guild_settings: opts.guild_settings.clone(),
+        subscriptions: opts.subscriptions.clone(),
+        poll_interval: std::time::Duration::from_secs(opts.poll_interval),
+    }
+}
+
+#[allow(unused_variables)]
+fn build_extractor(args: &CliOpts) -> anyhow::Result<Extractor> {
+    let extractor = Extractor::default().with_transport(build_transport(args)?);
+    #[cfg(feature = "sqlite-cache")]
+    if let Some(path) = &args.cache_db {
+        let cache =
+            bananagrabber::cache::SqliteCache::open(path, bananagrabber::cache::DEFAULT_TTL)?;
+        return Ok(extractor.with_cache(cache));
+    }
+    Ok(extractor)
+}
+
+fn build_transport(args: &CliOpts) -> anyhow::Result<Arc<dyn HttpTransport>> {
+    if let Some(path) = &args.replay {
+        return Ok(Arc::new(ReplayTransport::load(path)?));
+    }
+    let network = Arc::new(ReqwestTransport::default());
+    Ok(match &args.record {
+        Some(path) => Arc::new(RecordingTransport::new(network, path)),
+        None => network,
+    })
+}
+
 pub fn setup_logger(level: u8) {
     let mut builder = pretty_env_logger::formatted_timed_builder();
 
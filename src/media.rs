@@ -1,4 +1,172 @@
-#[derive(Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Media {
     pub url: String,
+    /// The host or embed provider the media came from, if known
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+/// What sort of thing the media url points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Video,
+    Gif,
+    Image,
+    /// A page on a media host, e.g. a youtube link
+    Page,
+}
+
+impl Media {
+    pub fn new<S: Into<String>>(url: S) -> Media {
+        Media {
+            url: url.into(),
+            provider: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    pub fn with_provider<S: Into<String>>(mut self, provider: S) -> Media {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    pub fn with_dimensions(mut self, width: Option<u32>, height: Option<u32>) -> Media {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// The mime type, going by the file extension
+    pub fn mime(&self) -> Option<&'static str> {
+        if self.url.contains("format=mp4") {
+            return Some("video/mp4");
+        }
+        let url = url::Url::parse(&self.url).ok()?;
+        if is_vreddit_dash(&url) {
+            return Some("video/mp4");
+        }
+        let path = url.path().to_ascii_lowercase();
+        let ext = path.rsplit_once('.')?.1;
+        Some(match ext {
+            "mp4" => "video/mp4",
+            "webm" => "video/webm",
+            "gif" => "image/gif",
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            _ => return None,
+        })
+    }
+
+    /// The file extension to save the media under
+    pub fn extension(&self) -> Option<&'static str> {
+        Some(match self.mime()? {
+            "video/mp4" => "mp4",
+            "video/webm" => "webm",
+            "image/gif" => "gif",
+            "image/jpeg" => "jpg",
+            "image/png" => "png",
+            _ => return None,
+        })
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        // reddit's gif transcodes keep the .gif path, with a `format=mp4` query
+        if self.url.contains("format=mp4") {
+            return MediaKind::Video;
+        }
+        match self.mime() {
+            Some("image/gif") => MediaKind::Gif,
+            Some(m) if m.starts_with("video/") => MediaKind::Video,
+            Some(_) => MediaKind::Image,
+            None => MediaKind::Page,
+        }
+    }
+}
+
+/// Older v.redd.it fallbacks are mp4s named like `DASH_4_8_M`, with no extension
+fn is_vreddit_dash(url: &url::Url) -> bool {
+    url.host_str() == Some("v.redd.it")
+        && url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .is_some_and(|name| name.starts_with("DASH_") && !name.contains('.'))
+}
+
+/// Check if the url already points at a media file
+pub fn is_direct_media(url: &url::Url) -> bool {
+    const EXTENSIONS: &[&str] = &[".mp4", ".webm", ".gif", ".jpg", ".jpeg", ".png"];
+    let path = url.path().to_ascii_lowercase();
+    EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// Check if the url is hosted on `domain` or one of its subdomains
+pub fn host_matches(url: &url::Url, domain: &str) -> bool {
+    match url.host_str() {
+        Some(host) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => false,
+    }
+}
+
+/// The referer a host wants before it hands over its media
+pub fn referer(url: &url::Url) -> Option<&'static str> {
+    // v.redd.it fallbacks answer 403 to requests that don't come from reddit
+    if host_matches(url, "redd.it") {
+        Some("https://www.reddit.com/")
+    } else {
+        None
+    }
+}
+
+/// Check if the url is media, either a file or a page on a media host
+pub fn is_media_link(url: &url::Url) -> bool {
+    const MEDIA_HOSTS: &[&str] = &[
+        "i.redd.it",
+        "v.redd.it",
+        "imgur.com",
+        "gfycat.com",
+        "redgifs.com",
+        "streamable.com",
+        "giphy.com",
+        "youtube.com",
+        "youtu.be",
+        "clips.twitch.tv",
+    ];
+    is_direct_media(url) || MEDIA_HOSTS.iter().any(|h| host_matches(url, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vreddit_dash_is_mp4() {
+        let old = Media::new("https://v.redd.it/6zyfsfjjlxz11/DASH_4_8_M?source=fallback");
+        assert_eq!(old.kind(), MediaKind::Video);
+        assert_eq!(old.extension(), Some("mp4"));
+        let new = Media::new("https://v.redd.it/9yk2xq3c1dd81/DASH_720.mp4?source=fallback");
+        assert_eq!(new.mime(), Some("video/mp4"));
+        let playlist = Media::new("https://v.redd.it/9yk2xq3c1dd81/DASHPlaylist.mpd");
+        assert_eq!(playlist.kind(), MediaKind::Page);
+    }
+
+    #[test]
+    fn referer_for_reddit_media() {
+        let referer_of = |u: &str| referer(&url::Url::parse(u).unwrap());
+        assert_eq!(
+            referer_of("https://v.redd.it/6zyfsfjjlxz11/DASH_4_8_M?source=fallback"),
+            Some("https://www.reddit.com/")
+        );
+        assert_eq!(
+            referer_of("https://i.redd.it/kaopcso5hqw61.png"),
+            Some("https://www.reddit.com/")
+        );
+        assert_eq!(referer_of("https://i.imgur.com/abc.mp4"), None);
+    }
 }
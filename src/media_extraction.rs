@@ -1,84 +1,373 @@
-use crate::argparse;
+use crate::cache::{Cache, MemoryCache};
+use crate::media::Media;
+use crate::metrics;
 use crate::reddit;
 use crate::reddit::ApiResponse;
 use crate::reddit::PostMediaSource;
-use anyhow::Context;
+use crate::reddit::PostSummary;
+use crate::reddit::Quote;
+use crate::reddit::RedditClient;
+use crate::reddit::RedditError;
+use crate::reddit::Unavailable;
+use crate::resolvers::ResolverChain;
+use crate::transport::HttpTransport;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 const CROSS_POST_RETRIES: usize = 10;
 
-pub fn check_saved_responses(opts: &argparse::Test) -> anyhow::Result<()> {
-    {
-        use std::fs;
-        for entry in fs::read_dir(&opts.file)? {
-            let entry = entry?;
-            log::debug!("begin {:?}", entry.path());
-            let f = fs::File::open(&entry.path())?;
-            let resp: anyhow::Result<reddit::ApiResponse> =
-                serde_json::from_reader(f).context("deserialize api response");
-            match resp {
-                Ok(r) => {
-                    let post = reddit::get_post_from_response(&r);
-                    log::debug!("{:?}: {:#?}", entry.path(), post);
-                    if let Ok(p) = post {
-                        let media = reddit::scan_for_media(p);
-                        log::info!("Media({:?}): {:?}", entry.path(), media);
-                    }
-                }
-                Err(e) => {
-                    log::warn!("{:#?}", e);
-                }
-            }
+/// What came of following a reddit link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MediaOutcome {
+    Found(Vec<Media>),
+    /// The post was taken down, or is somewhere we can't see it
+    Unavailable(Unavailable),
+    /// A self post with nothing but text
+    Text(Quote),
+    NotFound,
+}
+
+/// An extraction along with how it got there, for reporting
+#[derive(Debug)]
+pub struct Extraction {
+    /// The post the media came from, after following any cross posts
+    pub post: Option<PostSummary>,
+    /// The links that were followed and what the resolvers did, in order
+    pub trace: Vec<String>,
+    pub outcome: anyhow::Result<MediaOutcome>,
+}
+
+/// A rough classification of why an extraction failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Reddit told us to slow down
+    RateLimited,
+    /// Reddit answered with an unexpected status
+    HttpStatus,
+    /// Reddit could not be reached
+    Network,
+    /// The response was not what we know how to read
+    Parse,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn of(e: &anyhow::Error) -> ErrorKind {
+        if let Some(r) = e.downcast_ref::<RedditError>() {
+            return match r {
+                RedditError::TooManyRequests => ErrorKind::RateLimited,
+                _ => ErrorKind::HttpStatus,
+            };
+        }
+        if e.downcast_ref::<reqwest::Error>().is_some() {
+            ErrorKind::Network
+        } else if e.downcast_ref::<serde_json::Error>().is_some() {
+            ErrorKind::Parse
+        } else {
+            ErrorKind::Other
         }
     }
-    Ok(())
 }
 
-pub async fn fetch_url(opts: &argparse::ExtractMediaUrl) -> anyhow::Result<()> {
-    let media = fetch_url_through_cross_posts(&opts.url).await?;
-    match media {
-        Some(m) => println!("{}", m),
-        None => log::warn!("could not find media"),
-    }
-    Ok(())
+#[derive(Default)]
+struct Trace {
+    post: Option<PostSummary>,
+    steps: Vec<String>,
 }
 
-pub async fn fetch_url_through_cross_posts(url: &str) -> anyhow::Result<Option<String>> {
-    let mut xpost_retries = 0;
-    let mut url = url.to_string();
-    while xpost_retries < CROSS_POST_RETRIES {
-        match fetch_and_extract_source(&url).await? {
-            Some(PostMediaSource::Media(m)) => return Ok(Some(m.url)),
-            Some(PostMediaSource::CrossPost(u)) => url = u,
-            None => return Ok(None),
+/// Resolves reddit links to the media they point at.
+///
+/// The reddit client, resolvers and cache can all be swapped out,
+/// by default every resolver is registered and results are cached in memory.
+pub struct Extractor {
+    client: RedditClient,
+    resolvers: ResolverChain,
+    cache: Arc<dyn Cache>,
+}
+
+impl Default for Extractor {
+    fn default() -> Self {
+        Extractor {
+            client: RedditClient::default(),
+            resolvers: ResolverChain::default(),
+            cache: Arc::new(MemoryCache::default()),
         }
-        xpost_retries += 1;
     }
-    Err(anyhow::anyhow!(
-        "could not get media after {} cross posts",
-        xpost_retries
-    ))
 }
 
-pub async fn save_url(opts: &argparse::FetchTestCase) -> anyhow::Result<()> {
-    let client = reddit::RedditClient;
-    let resp = client.get_url_as::<serde_json::Value>(&opts.url).await?;
-    println!("{}", serde_json::to_string_pretty(&resp)?);
-    Ok(())
+impl Extractor {
+    pub fn with_client(mut self, client: RedditClient) -> Extractor {
+        self.client = client;
+        self
+    }
+
+    pub fn with_resolvers(mut self, resolvers: ResolverChain) -> Extractor {
+        self.resolvers = resolvers;
+        self
+    }
+
+    /// Send every request, to reddit and the media hosts, through the transport
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Extractor {
+        self.client = RedditClient::with_transport(transport.clone());
+        self.resolvers = self.resolvers.with_transport(transport);
+        self
+    }
+
+    pub fn with_cache<C: Cache + 'static>(self, cache: C) -> Extractor {
+        self.with_shared_cache(Arc::new(cache))
+    }
+
+    /// Use a cache that is also used elsewhere
+    pub fn with_shared_cache(mut self, cache: Arc<dyn Cache>) -> Extractor {
+        self.cache = cache;
+        self
+    }
+
+    /// Write out anything the cache is holding on to
+    pub fn flush(&self) {
+        self.cache.flush();
+    }
+
+    pub fn client(&self) -> &RedditClient {
+        &self.client
+    }
+
+    /// Find the media for a reddit link, using the cached result if there is one
+    pub async fn extract(&self, url: &str) -> anyhow::Result<MediaOutcome> {
+        if let Some(outcome) = self.cache.get(url) {
+            log::debug!("cache hit for {}", url);
+            metrics::cache_lookup(true);
+            let outcome = Ok(outcome);
+            record_metrics(&outcome);
+            return outcome;
+        }
+        metrics::cache_lookup(false);
+        let outcome = self
+            .fetch_url_through_cross_posts(url, &mut Trace::default())
+            .await;
+        record_metrics(&outcome);
+        let outcome = outcome?;
+        self.cache.put(url, &outcome);
+        Ok(outcome)
+    }
+
+    /// Find the media for a reddit link, keeping track of the post and each step taken.
+    ///
+    /// The cache only holds outcomes, so a cached result comes without the post.
+    pub async fn extract_detailed(&self, url: &str) -> Extraction {
+        if let Some(outcome) = self.cache.get(url) {
+            log::debug!("cache hit for {}", url);
+            metrics::cache_lookup(true);
+            let outcome = Ok(outcome);
+            record_metrics(&outcome);
+            return Extraction {
+                post: None,
+                trace: vec![format!("cached result for {}", url)],
+                outcome,
+            };
+        }
+        metrics::cache_lookup(false);
+        let mut trace = Trace::default();
+        let outcome = self.fetch_url_through_cross_posts(url, &mut trace).await;
+        record_metrics(&outcome);
+        if let Ok(o) = &outcome {
+            self.cache.put(url, o);
+        }
+        Extraction {
+            post: trace.post,
+            trace: trace.steps,
+            outcome,
+        }
+    }
+
+    /// Follow the reddit link to its media, then hand that to
+    /// the resolvers to get direct links for external hosts
+    async fn fetch_url_through_cross_posts(
+        &self,
+        url: &str,
+        trace: &mut Trace,
+    ) -> anyhow::Result<MediaOutcome> {
+        let mut xpost_retries = 0;
+        let mut url = url.to_string();
+        while xpost_retries < CROSS_POST_RETRIES {
+            trace.steps.push(format!("fetched {}", url));
+            let source = match self.fetch_and_extract_source(&url, trace).await {
+                Ok(s) => s,
+                Err(e) => match e.downcast_ref::<RedditError>() {
+                    Some(RedditError::Unavailable(u)) => {
+                        return Ok(MediaOutcome::Unavailable(u.clone()))
+                    }
+                    _ => return Err(e),
+                },
+            };
+            match source {
+                Some(PostMediaSource::Media(m)) => {
+                    let media = self.resolvers.resolve_all_traced(m, &mut trace.steps).await;
+                    return Ok(MediaOutcome::Found(media));
+                }
+                Some(PostMediaSource::CrossPost(u)) => {
+                    trace.steps.push(format!("cross post to {}", u));
+                    url = u
+                }
+                Some(PostMediaSource::Unavailable(u)) => return Ok(MediaOutcome::Unavailable(u)),
+                Some(PostMediaSource::Text(q)) => return Ok(MediaOutcome::Text(q)),
+                None => return Ok(MediaOutcome::NotFound),
+            }
+            xpost_retries += 1;
+        }
+        Err(anyhow::anyhow!(
+            "could not get media after {} cross posts",
+            xpost_retries
+        ))
+    }
+
+    async fn fetch_and_extract_source(
+        &self,
+        url: &str,
+        trace: &mut Trace,
+    ) -> anyhow::Result<Option<PostMediaSource>> {
+        let resp = self.client.get_info(url).await?;
+        if let Ok(post) = reddit::get_post_from_response(&resp) {
+            trace.post = Some(post.summary());
+        }
+        let comment_id = reddit::comment_id_from_url(url);
+        extract_media_from_respsonse(&resp, comment_id.as_deref())
+    }
 }
 
-async fn fetch_and_extract_source(url: &str) -> anyhow::Result<Option<PostMediaSource>> {
-    let resp = fetch_url_str(url).await?;
-    extract_media_from_respsonse(&resp)
+fn record_metrics(outcome: &anyhow::Result<MediaOutcome>) {
+    match outcome {
+        Ok(MediaOutcome::Found(media)) => metrics::extraction(
+            "found",
+            Some(
+                media
+                    .first()
+                    .and_then(|m| m.provider.as_deref())
+                    .unwrap_or("reddit"),
+            ),
+        ),
+        Ok(MediaOutcome::Unavailable(_)) => metrics::extraction("unavailable", None),
+        Ok(MediaOutcome::Text(_)) => metrics::extraction("text", None),
+        Ok(MediaOutcome::NotFound) => metrics::extraction("not_found", None),
+        Err(_) => metrics::extraction("error", None),
+    }
 }
 
-fn extract_media_from_respsonse(resp: &ApiResponse) -> anyhow::Result<Option<PostMediaSource>> {
-    let post = reddit::get_post_from_response(&resp)?;
+/// Prefer the media of the targeted comment, if there is one
+pub fn extract_media_from_respsonse(
+    resp: &ApiResponse,
+    comment_id: Option<&str>,
+) -> anyhow::Result<Option<PostMediaSource>> {
+    if let Some(id) = comment_id {
+        match reddit::get_comment_from_response(resp, id) {
+            Some(comment) => {
+                log::debug!("{:#?}", comment);
+                if let Some(source) = reddit::scan_comment_for_media(comment)? {
+                    return Ok(Some(source));
+                }
+                log::debug!("comment {} has no media, using the post", id);
+            }
+            None => log::warn!("could not find comment {} in response", id),
+        }
+    }
+
+    let post = reddit::get_post_from_response(resp)?;
     log::debug!("{:#?}", post);
     reddit::scan_for_media(post)
 }
 
-pub async fn fetch_url_str(url: &str) -> anyhow::Result<ApiResponse> {
-    let client = reddit::RedditClient;
-    let resp = client.get_info(url).await?;
-    Ok(resp)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Cassette, ReplayTransport};
+
+    const CROSS_POST: &str = include_str!("../sample_responses/cassettes/cross_post.json");
+    const PRIVATE: &str = include_str!("../sample_responses/cassettes/private.json");
+    const TOO_MANY_REQUESTS: &str =
+        include_str!("../sample_responses/cassettes/too_many_requests.json");
+
+    fn replay(cassette: &str, url: &str) -> anyhow::Result<MediaOutcome> {
+        let cassette: Cassette = serde_json::from_str(cassette).unwrap();
+        let extractor = Extractor::default()
+            .with_resolvers(ResolverChain::new())
+            .with_transport(Arc::new(ReplayTransport::new(cassette)));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(extractor.extract(url))
+    }
+
+    #[test]
+    fn replay_cross_post_through_redirects() {
+        let outcome = replay(
+            CROSS_POST,
+            "https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy",
+        )
+        .unwrap();
+        match outcome {
+            MediaOutcome::Found(media) => assert_eq!(
+                media.iter().map(|m| m.url.as_str()).collect::<Vec<_>>(),
+                vec!["https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?"]
+            ),
+            other => panic!("expected media, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replay_cross_post_trace() {
+        let cassette: Cassette = serde_json::from_str(CROSS_POST).unwrap();
+        let extractor = Extractor::default()
+            .with_resolvers(ResolverChain::new())
+            .with_transport(Arc::new(ReplayTransport::new(cassette)));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let extraction =
+            rt.block_on(extractor.extract_detailed(
+                "https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy",
+            ));
+        assert!(matches!(extraction.outcome, Ok(MediaOutcome::Found(_))));
+        assert_eq!(extraction.post.unwrap().id, "saj6w9");
+        assert_eq!(
+            extraction.trace,
+            vec![
+                "fetched https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy",
+                "cross post to https://v.redd.it/dkczbt15n2r71",
+                "fetched https://v.redd.it/dkczbt15n2r71",
+            ]
+        );
+
+        let cached =
+            rt.block_on(extractor.extract_detailed(
+                "https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy",
+            ));
+        assert!(matches!(cached.outcome, Ok(MediaOutcome::Found(_))));
+        assert_eq!(
+            cached.trace,
+            vec![
+                "cached result for https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy"
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_private_subreddit() {
+        let outcome = replay(
+            PRIVATE,
+            "https://www.reddit.com/r/secretgifs/comments/s1k2q9/hidden",
+        )
+        .unwrap();
+        assert_eq!(outcome, MediaOutcome::Unavailable(Unavailable::Private));
+    }
+
+    #[test]
+    fn replay_too_many_requests() {
+        let err = replay(
+            TOO_MANY_REQUESTS,
+            "https://www.reddit.com/r/gifs/comments/9zgyim/heres_what_a_rocket_launch_looks_like_from_the",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RedditError>(),
+            Some(RedditError::TooManyRequests)
+        ));
+        assert_eq!(ErrorKind::of(&err), ErrorKind::RateLimited);
+    }
 }
@@ -0,0 +1,131 @@
+//! Counters for how extraction is going, exported in the prometheus text
+//! format with the `metrics` feature. Without it every call here does nothing.
+#[cfg(feature = "metrics")]
+pub use imp::render;
+pub use imp::{
+    cache_lookup, discord_error, extraction, rate_limited, reddit_request, shard_connected,
+};
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use prometheus::{
+        register_histogram_with_registry, register_int_counter_vec_with_registry,
+        register_int_gauge_vec_with_registry, Encoder, Histogram, IntCounterVec, IntGaugeVec,
+        Registry, TextEncoder,
+    };
+    use std::time::Duration;
+
+    lazy_static::lazy_static! {
+        static ref REGISTRY: Registry = Registry::new_custom(Some("bananagrabber".to_string()), None).unwrap();
+        static ref EXTRACTIONS: IntCounterVec = register_int_counter_vec_with_registry!(
+            "extractions_total",
+            "Links extracted, by outcome and the provider of the first media",
+            &["outcome", "provider"],
+            REGISTRY
+        )
+        .unwrap();
+        static ref REDDIT_REQUESTS: Histogram = register_histogram_with_registry!(
+            "reddit_request_seconds",
+            "Time taken by each request to reddit, including redirects",
+            vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+            REGISTRY
+        )
+        .unwrap();
+        static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec_with_registry!(
+            "rate_limited_total",
+            "Requests held back by our own limiter, or rejected by reddit with a 429",
+            &["by"],
+            REGISTRY
+        )
+        .unwrap();
+        static ref CACHE: IntCounterVec = register_int_counter_vec_with_registry!(
+            "cache_lookups_total",
+            "Extraction cache lookups, by hit or miss",
+            &["result"],
+            REGISTRY
+        )
+        .unwrap();
+        static ref DISCORD_ERRORS: IntCounterVec = register_int_counter_vec_with_registry!(
+            "discord_errors_total",
+            "Failed calls to the discord api, by what the bot was doing",
+            &["action"],
+            REGISTRY
+        )
+        .unwrap();
+        static ref SHARDS: IntGaugeVec = register_int_gauge_vec_with_registry!(
+            "shard_connected",
+            "Whether each of the bot's shards is connected to the gateway",
+            &["shard"],
+            REGISTRY
+        )
+        .unwrap();
+    }
+
+    pub fn extraction(outcome: &str, provider: Option<&str>) {
+        EXTRACTIONS
+            .with_label_values(&[outcome, provider.unwrap_or("none")])
+            .inc();
+    }
+
+    pub fn reddit_request(elapsed: Duration) {
+        REDDIT_REQUESTS.observe(elapsed.as_secs_f64());
+    }
+
+    /// `by` is `limiter` when we held a request back, `reddit` for a 429
+    pub fn rate_limited(by: &str) {
+        RATE_LIMITED.with_label_values(&[by]).inc();
+    }
+
+    pub fn cache_lookup(hit: bool) {
+        CACHE
+            .with_label_values(&[if hit { "hit" } else { "miss" }])
+            .inc();
+    }
+
+    pub fn discord_error(action: &str) {
+        DISCORD_ERRORS.with_label_values(&[action]).inc();
+    }
+
+    pub fn shard_connected(shard: u64, connected: bool) {
+        SHARDS
+            .with_label_values(&[&shard.to_string()])
+            .set(connected as i64);
+    }
+
+    /// Everything, in the prometheus text exposition format
+    pub fn render() -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&REGISTRY.gather(), &mut buf)
+            .expect("metrics encode");
+        String::from_utf8(buf).expect("metrics are utf8")
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use std::time::Duration;
+
+    pub fn extraction(_outcome: &str, _provider: Option<&str>) {}
+    pub fn reddit_request(_elapsed: Duration) {}
+    pub fn rate_limited(_by: &str) {}
+    pub fn cache_lookup(_hit: bool) {}
+    pub fn discord_error(_action: &str) {}
+    pub fn shard_connected(_shard: u64, _connected: bool) {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counters() {
+        extraction("found", Some("imgur"));
+        cache_lookup(true);
+        let text = render();
+        assert!(
+            text.contains(r#"bananagrabber_extractions_total{outcome="found",provider="imgur"}"#)
+        );
+        assert!(text.contains(r#"bananagrabber_cache_lookups_total{result="hit"}"#));
+    }
+}
@@ -0,0 +1,104 @@
+//! Keep the requests to reddit under its rate limit, no matter how many
+//! extractions are running at once.
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Reddit allows unauthenticated clients around 60 requests a minute
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+/// Requests that can go out back to back before the limit kicks in
+pub const DEFAULT_BURST: u32 = 10;
+
+/// Requests are let through at a steady rate, with a burst allowance
+/// so a single extraction (and its redirects) is not slowed down.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    burst: u32,
+    /// When the next request would go out if requests had been perfectly spaced
+    next: Mutex<Option<Instant>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::per_minute(DEFAULT_REQUESTS_PER_MINUTE, DEFAULT_BURST)
+    }
+}
+
+impl RateLimiter {
+    pub fn per_minute(requests: u32, burst: u32) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs(60) / requests.max(1),
+            burst: burst.max(1),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Let every request through immediately
+    pub fn unlimited() -> RateLimiter {
+        RateLimiter {
+            interval: Duration::ZERO,
+            burst: 1,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Wait until a request is allowed to go out
+    pub async fn wait(&self) {
+        let now = Instant::now();
+        let at = self.reserve(now);
+        if at > now {
+            crate::metrics::rate_limited("limiter");
+            tokio::time::sleep_until(at).await;
+        }
+    }
+
+    /// Claim the next slot, returning when it may be used
+    fn reserve(&self, now: Instant) -> Instant {
+        let mut next = self.next.lock().unwrap();
+        let scheduled = match *next {
+            Some(n) if n > now => n,
+            _ => now,
+        };
+        *next = Some(scheduled + self.interval);
+        let window = self.interval * (self.burst - 1);
+        match scheduled.checked_sub(window) {
+            Some(at) if at > now => at,
+            _ => now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_goes_out_immediately() {
+        let limiter = RateLimiter::per_minute(60, 3);
+        let now = Instant::now();
+        let slots: Vec<Instant> = (0..5).map(|_| limiter.reserve(now)).collect();
+        assert_eq!(&slots[..3], &[now, now, now]);
+        assert_eq!(slots[3], now + Duration::from_secs(1));
+        assert_eq!(slots[4], now + Duration::from_secs(2));
+    }
+
+    #[test]
+    fn idle_time_refills_the_burst() {
+        let limiter = RateLimiter::per_minute(60, 2);
+        let now = Instant::now();
+        for _ in 0..4 {
+            limiter.reserve(now);
+        }
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later), later);
+        assert_eq!(limiter.reserve(later), later);
+    }
+
+    #[test]
+    fn unlimited_never_waits() {
+        let limiter = RateLimiter::unlimited();
+        let now = Instant::now();
+        assert!((0..100).all(|_| limiter.reserve(now) == now));
+    }
+}
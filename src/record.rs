@@ -0,0 +1,99 @@
+//! The json report of an extraction, used by `--format json` and the http server.
+//! The schema is described in the README, keep the two in step.
+use crate::media::MediaKind;
+use crate::media_extraction::ErrorKind;
+use crate::reddit::{PostSummary, Quote};
+use crate::{Extraction, Media, MediaOutcome};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Record {
+    /// The link as it was given
+    pub input: String,
+    pub status: Status,
+    /// The post the media came from, after following cross posts
+    pub post: Option<PostSummary>,
+    pub media: Vec<MediaRecord>,
+    /// Why the post can't be seen, when the status is `unavailable`
+    pub unavailable: Option<String>,
+    /// The post text, when the status is `text`
+    pub text: Option<Quote>,
+    pub trace: Vec<String>,
+    pub error: Option<ErrorRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Found,
+    Unavailable,
+    Text,
+    NotFound,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MediaRecord {
+    pub url: String,
+    pub provider: Option<String>,
+    pub kind: MediaKind,
+    pub mime: Option<&'static str>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorRecord {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl From<&Media> for MediaRecord {
+    fn from(m: &Media) -> MediaRecord {
+        MediaRecord {
+            url: m.url.clone(),
+            provider: m.provider.clone(),
+            kind: m.kind(),
+            mime: m.mime(),
+            width: m.width,
+            height: m.height,
+        }
+    }
+}
+
+impl Record {
+    pub fn new(input: &str, extraction: &Extraction) -> Record {
+        let mut record = Record {
+            input: input.to_string(),
+            status: Status::Error,
+            post: extraction.post.clone(),
+            media: Vec::new(),
+            unavailable: None,
+            text: None,
+            trace: extraction.trace.clone(),
+            error: None,
+        };
+        match &extraction.outcome {
+            Ok(MediaOutcome::Found(media)) => {
+                record.status = Status::Found;
+                record.media = media.iter().map(MediaRecord::from).collect();
+            }
+            Ok(MediaOutcome::Unavailable(u)) => {
+                record.status = Status::Unavailable;
+                record.unavailable = Some(u.to_string());
+            }
+            Ok(MediaOutcome::Text(q)) => {
+                record.status = Status::Text;
+                record.text = Some(q.clone());
+            }
+            Ok(MediaOutcome::NotFound) => record.status = Status::NotFound,
+            Err(e) => {
+                record.error = Some(ErrorRecord {
+                    kind: ErrorKind::of(e),
+                    message: format!("{:#}", e),
+                })
+            }
+        }
+        record
+    }
+}
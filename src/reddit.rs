@@ -1,10 +1,17 @@
-use crate::media::Media;
+use crate::media::{is_media_link, Media};
+use crate::metrics;
+use crate::rate_limit::RateLimiter;
+use crate::transport::{HttpRequest, HttpTransport, ReqwestTransport};
 use anyhow::Result;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 const REDIRECTS: usize = 10;
+/// Posts to read from a subreddit listing at a time
+const LISTING_LIMIT: usize = 25;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -12,8 +19,29 @@ pub struct ApiResponse {
     data: Vec<ApiObject>,
 }
 
+impl ApiResponse {
+    /// Drop the comments, except the one the link points at, so the
+    /// response is small enough to keep as a fixture.
+    ///
+    /// Serializing only writes the fields we parse, so the rest of what
+    /// reddit sent is already gone.
+    pub fn minimize(self, comment_id: Option<&str>) -> ApiResponse {
+        let comment = comment_id
+            .and_then(|id| get_comment_from_response(&self, id))
+            .cloned();
+        let mut data: Vec<ApiObject> = self.data.into_iter().take(1).collect();
+        if let Some(comment) = comment {
+            data.push(ApiObject::Listing(ApiListing {
+                children: vec![ApiObject::Comment(comment)],
+            }));
+        }
+        ApiResponse { data }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", content = "data")]
+#[allow(clippy::large_enum_variant)]
 pub enum ApiObject {
     Listing(ApiListing),
     #[serde(rename = "t3")]
@@ -33,74 +61,429 @@ pub struct ApiListing {
 pub struct MoreInfo {}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CommentInfo {}
+pub struct CommentInfo {
+    #[serde(default)]
+    id: String,
+    author: Option<String>,
+    /// markdown, html escaped
+    #[serde(default)]
+    body: String,
+    /// Images and gifs posted inline, keyed by the id used in the body
+    media_metadata: Option<HashMap<String, MediaMetadata>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaMetadata {
+    status: Option<String>,
+    /// `Image` or `AnimatedImage`
+    #[serde(rename = "e")]
+    kind: Option<String>,
+    /// mime type
+    #[serde(rename = "m")]
+    mime: Option<String>,
+    #[serde(rename = "s")]
+    source: Option<MediaMetadataSource>,
+}
+
+/// The full size rendition, images have `u` while animations have `gif`/`mp4`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaMetadataSource {
+    /// html escaped
+    u: Option<String>,
+    gif: Option<String>,
+    mp4: Option<String>,
+    #[serde(rename = "x")]
+    width: Option<u32>,
+    #[serde(rename = "y")]
+    height: Option<u32>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostInfo {
+    #[serde(default)]
+    id: String,
     subreddit: String,
     title: String,
+    author: Option<String>,
+    /// markdown, html escaped
+    selftext: Option<String>,
+    selftext_html: Option<String>,
+    /// Images posted inline in a self post, keyed by the id used in the text
+    media_metadata: Option<HashMap<String, MediaMetadata>>,
+    /// Set when the post was taken down, `moderator`, `deleted`, `reddit`, ...
+    removed_by_category: Option<String>,
     is_reddit_media_domain: bool,
     secure_media: Option<RedditMedia>,
     media: Option<RedditMedia>,
+    secure_media_embed: Option<MediaEmbed>,
+    preview: Option<Preview>,
     domain: String,
     over_18: bool,
     is_video: bool,
     url: String,
+    #[serde(default)]
+    score: i64,
+    /// e.g. `/r/gifs/comments/q0x1pe/the_edgeon_galaxy/`
+    #[serde(default)]
+    permalink: String,
+    /// Pinned to the top of the subreddit by the moderators
+    #[serde(default)]
+    stickied: bool,
+}
+
+/// The parts of a post worth reporting alongside its media
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostSummary {
+    pub id: String,
+    pub subreddit: String,
+    pub title: String,
+    pub nsfw: bool,
+}
+
+impl PostInfo {
+    pub fn summary(&self) -> PostSummary {
+        PostSummary {
+            id: self.id.clone(),
+            subreddit: self.subreddit.clone(),
+            title: self.title.clone(),
+            nsfw: self.over_18,
+        }
+    }
+}
+
+/// How a subreddit listing is ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingSort {
+    New,
+    Hot,
+    /// The top posts of the day
+    Top,
+}
+
+impl ListingSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingSort::New => "new",
+            ListingSort::Hot => "hot",
+            ListingSort::Top => "top",
+        }
+    }
+}
+
+impl std::str::FromStr for ListingSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ListingSort> {
+        match s {
+            "new" => Ok(ListingSort::New),
+            "hot" => Ok(ListingSort::Hot),
+            "top" => Ok(ListingSort::Top),
+            _ => anyhow::bail!("unknown sort {:?}, expected new, hot or top", s),
+        }
+    }
+}
+
+/// A post as it shows up in a subreddit listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListedPost {
+    pub id: String,
+    pub title: String,
+    pub score: i64,
+    pub nsfw: bool,
+    pub stickied: bool,
+    /// A link to the post, which can be handed to the extractor
+    pub url: String,
+}
+
+impl From<&PostInfo> for ListedPost {
+    fn from(post: &PostInfo) -> ListedPost {
+        ListedPost {
+            id: post.id.clone(),
+            title: post.title.clone(),
+            score: post.score,
+            nsfw: post.over_18,
+            stickied: post.stickied,
+            url: format!("https://www.reddit.com{}", post.permalink),
+        }
+    }
+}
+
+/// Check a subreddit name is one reddit would allow, before putting it in a url
+pub fn is_subreddit_name(name: &str) -> bool {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(r"^[A-Za-z0-9_]{2,21}$").unwrap();
+    }
+    RE.is_match(name)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RedditMedia {
+    #[serde(rename = "type")]
+    media_type: Option<String>,
     reddit_video: Option<RedditVideo>,
     oembed: Option<OEmbedVideo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OEmbedVideo {}
+pub struct OEmbedVideo {
+    provider_name: Option<String>,
+    provider_url: Option<String>,
+    #[serde(rename = "type")]
+    oembed_type: Option<String>,
+    title: Option<String>,
+    thumbnail_url: Option<String>,
+    /// The embed snippet, html escaped
+    html: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preview {
+    #[serde(default)]
+    images: Vec<PreviewImage>,
+    /// Reddit transcodes gifs to video, this is what makes them playable
+    reddit_video_preview: Option<RedditVideo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewImage {
+    source: ImageSource,
+    #[serde(default)]
+    variants: PreviewVariants,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreviewVariants {
+    gif: Option<PreviewVariant>,
+    mp4: Option<PreviewVariant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewVariant {
+    source: ImageSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSource {
+    /// html escaped
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+/// The iframe reddit would use to show third-party media
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaEmbed {
+    /// The embed snippet, html escaped
+    content: Option<String>,
+    media_domain_url: Option<String>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RedditVideo {
     scrubber_media_url: String,
     fallback_url: String,
+    width: Option<u32>,
+    height: Option<u32>,
     duration: f32,
     is_gif: bool,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PostMediaSource {
-    Media(Media),
+    Media(Vec<Media>),
     CrossPost(String),
+    Unavailable(Unavailable),
+    /// A self post without any media
+    Text(Quote),
+}
+
+/// The text of a self post, for quoting when there is no media
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quote {
+    pub title: String,
+    pub text: String,
+}
+
+/// Why a post's media can not be shown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Unavailable {
+    /// Taken down, with the `removed_by_category` reddit gave
+    Removed(String),
+    /// Deleted by its author
+    Deleted,
+    Private,
+    Quarantined,
+    Banned,
+    NotFound,
+}
+
+impl std::fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unavailable::Removed(by) => match by.as_str() {
+                "moderator" | "automod_filtered" => write!(f, "removed by the moderators"),
+                "reddit" | "anti_evil_ops" | "content_takedown" => write!(f, "removed by reddit"),
+                "copyright_takedown" => write!(f, "removed for copyright"),
+                other => write!(f, "removed ({})", other),
+            },
+            Unavailable::Deleted => write!(f, "deleted by its author"),
+            Unavailable::Private => write!(f, "in a private subreddit"),
+            Unavailable::Quarantined => write!(f, "in a quarantined subreddit"),
+            Unavailable::Banned => write!(f, "in a banned subreddit"),
+            Unavailable::NotFound => write!(f, "not found"),
+        }
+    }
+}
+
+/// The error body reddit sends with a failed request
+#[derive(Debug, Clone, Deserialize)]
+struct ApiError {
+    message: Option<String>,
+    reason: Option<String>,
 }
 
-// TODO Rate limiting
-pub struct RedditClient;
+#[derive(Debug)]
+pub enum RedditError {
+    /// The post or its subreddit can not be seen
+    Unavailable(Unavailable),
+    TooManyRequests,
+    Status(reqwest::StatusCode, String),
+}
+
+impl std::fmt::Display for RedditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RedditError::Unavailable(u) => write!(f, "post is {}", u),
+            RedditError::TooManyRequests => write!(f, "reddit is rate limiting us"),
+            RedditError::Status(status, msg) => write!(f, "reddit returned {}: {}", status, msg),
+        }
+    }
+}
+
+impl std::error::Error for RedditError {}
+
+/// Classify an unsuccessful response from reddit
+fn api_error(status: reqwest::StatusCode, body: &str) -> RedditError {
+    let err = serde_json::from_str::<ApiError>(body).ok();
+    let reason = err.as_ref().and_then(|e| e.reason.as_deref());
+    match (status, reason) {
+        (_, Some("private")) => RedditError::Unavailable(Unavailable::Private),
+        (_, Some("quarantined")) => RedditError::Unavailable(Unavailable::Quarantined),
+        (_, Some("banned")) => RedditError::Unavailable(Unavailable::Banned),
+        (reqwest::StatusCode::NOT_FOUND, _) => RedditError::Unavailable(Unavailable::NotFound),
+        (reqwest::StatusCode::TOO_MANY_REQUESTS, _) => RedditError::TooManyRequests,
+        _ => RedditError::Status(
+            status,
+            err.and_then(|e| e.message)
+                .unwrap_or_else(|| body.chars().take(200).collect()),
+        ),
+    }
+}
+
+/// Clones share the same rate limit
+#[derive(Clone)]
+pub struct RedditClient {
+    transport: Arc<dyn HttpTransport>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Default for RedditClient {
+    fn default() -> Self {
+        RedditClient::with_transport(Arc::new(ReqwestTransport::default()))
+    }
+}
 
 impl RedditClient {
+    /// Send requests through something other than the network, e.g. a replay of a cassette
+    pub fn with_transport(transport: Arc<dyn HttpTransport>) -> RedditClient {
+        RedditClient {
+            transport,
+            limiter: Arc::new(RateLimiter::default()),
+        }
+    }
+
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> RedditClient {
+        self.limiter = Arc::new(limiter);
+        self
+    }
+
     pub async fn get_info(&self, url: &str) -> Result<ApiResponse> {
         self.get_url_as(url).await
     }
 
     pub async fn get_url_as<T: DeserializeOwned + std::fmt::Debug>(&self, url: &str) -> Result<T> {
+        self.get_json_as(make_url_json(url)?).await
+    }
+
+    /// The newest posts of a subreddit, in the listing's order
+    pub async fn subreddit_posts(
+        &self,
+        subreddit: &str,
+        sort: ListingSort,
+    ) -> Result<Vec<ListedPost>> {
+        if !is_subreddit_name(subreddit) {
+            anyhow::bail!("{:?} is not a subreddit name", subreddit);
+        }
+        let mut url = reqwest::Url::parse(&format!(
+            "https://www.reddit.com/r/{}/{}.json",
+            subreddit,
+            sort.as_str()
+        ))?;
+        url.query_pairs_mut()
+            .append_pair("limit", &LISTING_LIMIT.to_string());
+        if sort == ListingSort::Top {
+            url.query_pairs_mut().append_pair("t", "day");
+        }
+        match self.get_json_as(url).await? {
+            ApiObject::Listing(listing) => Ok(listing
+                .children
+                .iter()
+                .filter_map(|c| match c {
+                    ApiObject::Post(p) => Some(ListedPost::from(p)),
+                    _ => None,
+                })
+                .collect()),
+            other => anyhow::bail!("expected a listing, found {:?}", other),
+        }
+    }
+
+    /// Fetch a url that already points at json, following redirects
+    async fn get_json_as<T: DeserializeOwned + std::fmt::Debug>(
+        &self,
+        mut full_url: reqwest::Url,
+    ) -> Result<T> {
         let mut redirect_count = 0;
-        let mut full_url = make_url_json(url)?;
 
         while redirect_count < REDIRECTS {
             redirect_count += 1;
             log::debug!("url: {:?}", full_url.as_str());
-            let policy = if is_json(&full_url) {
-                reqwest::redirect::Policy::default()
-            } else {
-                reqwest::redirect::Policy::none()
-            };
-            let http_client = reqwest::ClientBuilder::new().redirect(policy).build()?;
-            let resp = http_client.get(full_url).send().await?;
-
-            if resp.status().is_redirection() {
-                let new_loc = resp.headers().get("location").ok_or_else(|| {
-                    anyhow::anyhow!("redirect did not provide new location: {:?}", resp)
+            self.limiter.wait().await;
+            let start = std::time::Instant::now();
+            let resp = self
+                .transport
+                .send(HttpRequest::get(full_url.as_str()))
+                .await?;
+            metrics::reddit_request(start.elapsed());
+            let status = resp.status();
+
+            if status.is_redirection() {
+                let new_loc = resp.header("location").ok_or_else(|| {
+                    anyhow::anyhow!("redirect did not provide new location: {:?}", status)
                 })?;
-                full_url = make_url_json(new_loc.to_str()?)?;
+                let new_url = full_url.join(new_loc)?;
+                full_url = if is_json(&new_url) {
+                    new_url
+                } else {
+                    make_url_json(new_url.as_str())?
+                };
+            } else if !status.is_success() {
+                if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    metrics::rate_limited("reddit");
+                }
+                return Err(api_error(status, &resp.body).into());
             } else {
-                return Ok(resp.json::<T>().await?);
+                return resp.json::<T>();
             }
         }
         Err(anyhow::anyhow!(
@@ -111,38 +494,341 @@ impl RedditClient {
 }
 
 pub fn get_post_from_response(resp: &ApiResponse) -> anyhow::Result<&PostInfo> {
-    match &resp.data[0] {
-        ApiObject::Listing(listing) => match &listing.children[0] {
-            ApiObject::Post(post) => Ok(post),
+    match resp.data.first() {
+        Some(ApiObject::Listing(listing)) => match listing.children.first() {
+            Some(ApiObject::Post(post)) => Ok(post),
             _ => Err(anyhow::anyhow!("expected post")),
         },
         _ => Err(anyhow::anyhow!("expected listing")),
     }
 }
 
+/// Find a comment in the reply listing of a comment permalink
+pub fn get_comment_from_response<'a>(resp: &'a ApiResponse, id: &str) -> Option<&'a CommentInfo> {
+    resp.data.iter().skip(1).find_map(|obj| match obj {
+        ApiObject::Listing(listing) => listing.children.iter().find_map(|c| match c {
+            ApiObject::Comment(comment) if comment.id == id => Some(comment),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// The id of the comment a permalink points at, either the
+/// `comments/<post>/<slug>/<comment>` or `comments/<post>/comment/<comment>` form
+pub fn comment_id_from_url(url: &str) -> Option<String> {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(
+            r##"/comments/[a-z0-9]+/[^/?#]+/([a-z0-9]+)/?(?:[?#]|$)"##
+        ).unwrap();
+    }
+    RE.captures(url)?.get(1).map(|m| m.as_str().to_string())
+}
+
+/// Find media in a comment, its inline images or else the first link in its
+/// body to media or another post. Anything else, like a news article, leaves
+/// the post's own media to be used.
+pub fn scan_comment_for_media(comment: &CommentInfo) -> Result<Option<PostMediaSource>> {
+    let body = unescape_html(&comment.body);
+    let inline = inline_media(&body, comment.media_metadata.as_ref());
+    if !inline.is_empty() {
+        return Ok(Some(PostMediaSource::Media(inline)));
+    }
+
+    let link = links_in_text(&body).into_iter().find(|link| {
+        is_reddit_short_url(link)
+            || is_reddit_post_url(link)
+            || url::Url::parse(link)
+                .map(|u| is_media_link(&u))
+                .unwrap_or(false)
+    });
+    Ok(link.map(link_source))
+}
+
+/// Images and gifs embedded in the text, in the order they appear
+fn inline_media(text: &str, metadata: Option<&HashMap<String, MediaMetadata>>) -> Vec<Media> {
+    let metadata = match metadata {
+        Some(m) => m,
+        None => return Vec::new(),
+    };
+    let mut entries: Vec<(Option<usize>, &String, &MediaMetadata)> = metadata
+        .iter()
+        .map(|(id, meta)| (text.find(id.as_str()), id, meta))
+        .collect();
+    entries.sort_by_key(|(pos, id, _)| (pos.unwrap_or(usize::MAX), *id));
+    entries
+        .into_iter()
+        .filter_map(|(_, _, meta)| metadata_media(meta))
+        .collect()
+}
+
+fn metadata_media(meta: &MediaMetadata) -> Option<Media> {
+    if !matches!(meta.status.as_deref(), None | Some("valid")) {
+        return None;
+    }
+    let source = meta.source.as_ref()?;
+    let url = source
+        .mp4
+        .as_ref()
+        .or(source.gif.as_ref())
+        .or(source.u.as_ref())?;
+    Some(Media::new(unescape_html(url)).with_dimensions(source.width, source.height))
+}
+
+fn video_media(video: &RedditVideo) -> Result<Media> {
+    Ok(Media::new(strip_query_params(&video.fallback_url)?)
+        .with_dimensions(video.width, video.height))
+}
+
+/// The http links in some markdown text
+fn links_in_text(text: &str) -> Vec<&str> {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(
+            r##"https?://[^\s()\[\]<>"]+"##
+        ).unwrap();
+    }
+    RE.find_iter(text).map(|m| m.as_str()).collect()
+}
+
+/// The links to reddit posts in some text, e.g. a line of a chat log
+pub fn reddit_links_in_text(text: &str) -> Vec<&str> {
+    links_in_text(text)
+        .into_iter()
+        .filter(|l| is_reddit_short_url(l) || is_reddit_post_url(l))
+        .collect()
+}
+
+/// Links to other reddit posts are followed, anything else is treated as media
+fn link_source(link: &str) -> PostMediaSource {
+    if is_reddit_short_url(link) || is_reddit_post_url(link) {
+        PostMediaSource::CrossPost(link.to_string())
+    } else {
+        PostMediaSource::Media(vec![Media::new(link)])
+    }
+}
+
+/// Find the best media for the post, in order of preference:
+///
+/// 1. `media`, a reddit hosted video or a third-party embed
+/// 2. `secure_media`, same as above
+/// 3. `preview.reddit_video_preview`, reddit's video transcode of a gif
+/// 4. `preview.images[].variants`, reddit's mp4 (or gif) of the linked media
+/// 5. `url`, whatever the post links to
+///
+/// Self posts are instead scanned for inline images and media links,
+/// falling back to quoting the text.
+///
+/// Removed and deleted posts only keep media reddit still hosts,
+/// anything else is reported as unavailable.
 pub fn scan_for_media(post: &PostInfo) -> Result<Option<PostMediaSource>> {
-    if let Some(media) = &post.media {
-        if let Some(reddit_video) = &media.reddit_video {
-            return Ok(Some(PostMediaSource::Media(Media {
-                url: strip_query_params(&reddit_video.fallback_url)?,
-            })));
+    if let Some(reason) = unavailable_reason(post) {
+        for media in post.media.iter().chain(post.secure_media.iter()) {
+            if let Some(video) = &media.reddit_video {
+                return Ok(Some(PostMediaSource::Media(vec![video_media(video)?])));
+            }
+        }
+        return Ok(Some(PostMediaSource::Unavailable(reason)));
+    }
+
+    for media in post.media.iter().chain(post.secure_media.iter()) {
+        if let Some(m) = scan_reddit_media(post, media)? {
+            return Ok(Some(PostMediaSource::Media(vec![m])));
         }
     }
 
     if post.domain.starts_with("self.") {
-        return Ok(None);
+        return Ok(scan_self_post(post));
+    }
+
+    if let Some(preview) = &post.preview {
+        if let Some(video) = &preview.reddit_video_preview {
+            return Ok(Some(PostMediaSource::Media(vec![video_media(video)?])));
+        }
+    }
+
+    if let Some(variant) = post.preview.as_ref().and_then(preview_variant) {
+        let source = &variant.source;
+        return Ok(Some(PostMediaSource::Media(vec![Media::new(
+            unescape_html(&source.url),
+        )
+        .with_dimensions(Some(source.width), Some(source.height))])));
     }
 
     let url = post.url.clone();
-    let source = if is_reddit_short_url(&url) {
-        PostMediaSource::CrossPost(url)
-    } else {
-        PostMediaSource::Media(Media {
-            url: post.url.clone(),
-        })
+    if is_reddit_short_url(&url) {
+        return Ok(Some(PostMediaSource::CrossPost(url)));
+    }
+
+    Ok(Some(PostMediaSource::Media(vec![Media::new(url)])))
+}
+
+/// Inline images and outbound media links in the text of a self post,
+/// or the text itself if there is nothing else
+fn scan_self_post(post: &PostInfo) -> Option<PostMediaSource> {
+    let text = post
+        .selftext
+        .as_deref()
+        .filter(|t| !t.trim().is_empty())
+        .or(post.selftext_html.as_deref())
+        .map(unescape_html)
+        .unwrap_or_default();
+
+    let mut media = inline_media(&text, post.media_metadata.as_ref());
+    let inline_ids: Vec<&String> = post.media_metadata.iter().flat_map(|m| m.keys()).collect();
+    for link in links_in_text(&text) {
+        // new reddit links inline images as well as listing them in the metadata
+        if inline_ids.iter().any(|id| link.contains(id.as_str())) {
+            continue;
+        }
+        let is_media = url::Url::parse(link)
+            .map(|u| is_media_link(&u))
+            .unwrap_or(false);
+        if is_media && !media.iter().any(|m| m.url == link) {
+            media.push(Media::new(link));
+        }
+    }
+
+    if !media.is_empty() {
+        return Some(PostMediaSource::Media(media));
+    }
+
+    let text = post.selftext.as_deref().map(unescape_html)?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some(PostMediaSource::Text(Quote {
+        title: unescape_html(&post.title),
+        text,
+    }))
+}
+
+/// Check the signals reddit leaves on a post that was taken down
+fn unavailable_reason(post: &PostInfo) -> Option<Unavailable> {
+    match post.removed_by_category.as_deref() {
+        Some("deleted" | "author") => return Some(Unavailable::Deleted),
+        Some(by) => return Some(Unavailable::Removed(by.to_string())),
+        None => {}
+    }
+
+    match post.selftext.as_deref() {
+        Some("[removed]") => return Some(Unavailable::Removed("moderator".to_string())),
+        Some("[deleted]") => return Some(Unavailable::Deleted),
+        _ => {}
+    }
+
+    // a deleted author on a link post leaves the link pointing at the post itself
+    let deleted_author = post.author.as_deref() == Some("[deleted]");
+    let is_self = post.domain.starts_with("self.");
+    if deleted_author && !is_self && is_reddit_post_url(&post.url) {
+        return Some(Unavailable::Deleted);
+    }
+    None
+}
+
+/// Check if a url is a link to a reddit comment page
+fn is_reddit_post_url(text: &str) -> bool {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(
+            r##"^https://(www\.|old\.)?reddit\.com/r/[^/]+/comments/"##
+        ).unwrap();
+    }
+    RE.is_match(text)
+}
+
+fn scan_reddit_media(post: &PostInfo, media: &RedditMedia) -> Result<Option<Media>> {
+    if let Some(reddit_video) = &media.reddit_video {
+        return Ok(Some(video_media(reddit_video)?));
+    }
+
+    if let Some(oembed) = &media.oembed {
+        let embed = post
+            .secure_media_embed
+            .as_ref()
+            .and_then(|e| e.content.as_deref());
+        return Ok(Some(oembed_media(post, oembed, embed)));
+    }
+
+    Ok(None)
+}
+
+/// The first animated variant of the preview images, preferring mp4
+fn preview_variant(preview: &Preview) -> Option<&PreviewVariant> {
+    preview
+        .images
+        .iter()
+        .find_map(|i| i.variants.mp4.as_ref().or(i.variants.gif.as_ref()))
+}
+
+/// Use the oembed data to name the provider, and to find a url
+/// for it that discord knows how to embed natively.
+///
+/// Falls back to the post url when the embed can't be understood.
+fn oembed_media(post: &PostInfo, oembed: &OEmbedVideo, embed_html: Option<&str>) -> Media {
+    let url = oembed
+        .html
+        .as_deref()
+        .or(embed_html)
+        .and_then(iframe_src)
+        .and_then(|src| canonical_embed_url(&src))
+        .unwrap_or_else(|| post.url.clone());
+
+    let media = Media::new(url);
+    match &oembed.provider_name {
+        Some(provider) => media.with_provider(provider.clone()),
+        None => media,
+    }
+}
+
+/// Pull the `src` out of the (escaped) iframe snippet
+fn iframe_src(html: &str) -> Option<url::Url> {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(
+            r##"<iframe[^>]*\ssrc="([^"]+)""##
+        ).unwrap();
+    }
+    let html = unescape_html(html);
+    let src = RE.captures(&html)?.get(1)?.as_str();
+    url::Url::parse(src).ok()
+}
+
+/// Turn the embed player url into the url people would share.
+///
+/// Only providers we know how to map are handled, anything else
+/// should keep using the post url.
+fn canonical_embed_url(src: &url::Url) -> Option<String> {
+    let query = |key: &str| {
+        src.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
     };
+    let segments: Vec<&str> = src.path_segments()?.filter(|s| !s.is_empty()).collect();
 
-    Ok(Some(source))
+    match (src.host_str()?, segments.as_slice()) {
+        // embedly wraps the real player, and tells us the original url
+        ("cdn.embedly.com", _) => {
+            let inner = url::Url::parse(&query("src")?).ok()?;
+            canonical_embed_url(&inner)
+        }
+        ("www.youtube.com" | "youtube.com" | "www.youtube-nocookie.com", ["embed", id]) => {
+            Some(format!("https://www.youtube.com/watch?v={}", id))
+        }
+        ("clips.twitch.tv", ["embed"]) => {
+            Some(format!("https://clips.twitch.tv/{}", query("clip")?))
+        }
+        ("streamable.com", ["e" | "o" | "s", code]) => {
+            Some(format!("https://streamable.com/{}", code))
+        }
+        _ => None,
+    }
+}
+
+/// Reddit html-escapes embed snippets and urls inside its json
+fn unescape_html(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
 }
 
 fn strip_query_params(s: &str) -> anyhow::Result<String> {
@@ -153,15 +839,19 @@ fn strip_query_params(s: &str) -> anyhow::Result<String> {
 }
 
 fn is_json(url: &reqwest::Url) -> bool {
-    url.as_str().ends_with(".json")
+    url.path().ends_with(".json")
 }
 
 fn make_url_json(s: &str) -> anyhow::Result<reqwest::Url> {
-    let mut s = s.to_string();
-    if !is_reddit_short_url(&s) {
-        s.push_str("/.json");
+    let mut url = reqwest::Url::parse(s)?;
+    url.set_fragment(None);
+    let mut bare = url.clone();
+    bare.set_query(None);
+    if !is_reddit_short_url(bare.as_str()) {
+        // the query stays where it is, like `?context=3` on a comment permalink
+        let path = format!("{}/.json", url.path().trim_end_matches('/'));
+        url.set_path(&path);
     }
-    let url = reqwest::Url::parse(&s)?;
     Ok(url)
 }
 
@@ -189,6 +879,20 @@ mod tests {
     const VREDDIT: &str = include_str!("../sample_responses/vreddit.json");
     const VREDDIT_PREVIEW: &str = include_str!("../sample_responses/vreddit_preview.json");
     const CROSS_POST: &str = include_str!("../sample_responses/cross_post.json");
+    const YOUTUBE: &str = include_str!("../sample_responses/youtube.json");
+    const SECURE_MEDIA: &str = include_str!("../sample_responses/secure_media.json");
+    const REDDIT_VIDEO_PREVIEW: &str =
+        include_str!("../sample_responses/reddit_video_preview.json");
+    const PREVIEW_VARIANTS: &str = include_str!("../sample_responses/preview_variants.json");
+    const DIRECT_GIF_VARIANTS: &str = include_str!("../sample_responses/direct_gif_variants.json");
+    const VREDDIT_SHORT_URL: &str = include_str!("../sample_responses/vreddit_short_url.json");
+    const DELETED: &str = include_str!("../sample_responses/deleted.json");
+    const REMOVED: &str = include_str!("../sample_responses/removed.json");
+    const PRIVATE: &str = include_str!("../sample_responses/private.json");
+    const QUARANTINED: &str = include_str!("../sample_responses/quarantined.json");
+    const COMMENT_IMAGE: &str = include_str!("../sample_responses/comment_image.json");
+    const COMMENT_LINK: &str = include_str!("../sample_responses/comment_link.json");
+    const SELF_POST_MEDIA: &str = include_str!("../sample_responses/self_post_media.json");
 
     fn check_parse_and_subreddit(json: &str, subreddit: &str) {
         let resp = serde_json::from_str::<ApiResponse>(json).unwrap();
@@ -196,16 +900,59 @@ mod tests {
         assert_eq!(post.subreddit, subreddit);
     }
 
-    fn check_parse_and_media_source(json: &str, media: Option<PostMediaSource>) {
+    fn scan_fixture(json: &str) -> Option<PostMediaSource> {
         let resp = serde_json::from_str::<ApiResponse>(json).unwrap();
         let post = get_post_from_response(&resp).unwrap();
+        scan_for_media(post).unwrap()
+    }
 
-        assert_eq!(scan_for_media(post).unwrap(), media);
+    fn check_parse_and_media_source(json: &str, media: Option<PostMediaSource>) {
+        assert_eq!(scan_fixture(json), media);
     }
 
     fn check_parse_and_media(json: &str, media: Option<&str>) {
-        let wrapped_media = media.map(|s| PostMediaSource::Media(Media { url: s.to_string() }));
-        check_parse_and_media_source(json, wrapped_media)
+        check_parse_and_media_list(json, media.into_iter().collect())
+    }
+
+    fn check_parse_and_media_list(json: &str, media: Vec<&str>) {
+        let found: Vec<String> = match scan_fixture(json) {
+            Some(PostMediaSource::Media(m)) => m.into_iter().map(|m| m.url).collect(),
+            None => Vec::new(),
+            Some(other) => panic!("expected media, found {:?}", other),
+        };
+        assert_eq!(found, media);
+    }
+
+    fn check_parse_and_provider(json: &str, provider: &str) {
+        match scan_fixture(json) {
+            Some(PostMediaSource::Media(m)) => {
+                assert_eq!(m[0].provider.as_deref(), Some(provider))
+            }
+            other => panic!("expected media, found {:?}", other),
+        }
+    }
+
+    fn check_comment_media(json: &str, comment_id: &str, media: Option<PostMediaSource>) {
+        let resp = serde_json::from_str::<ApiResponse>(json).unwrap();
+        let comment = get_comment_from_response(&resp, comment_id).unwrap();
+        assert_eq!(scan_comment_for_media(comment).unwrap(), media);
+    }
+
+    fn check_parse_and_unavailable(json: &str, reason: Unavailable) {
+        check_parse_and_media_source(json, Some(PostMediaSource::Unavailable(reason)))
+    }
+
+    fn check_api_error_unavailable(status: u16, json: &str, reason: Unavailable) {
+        let status = reqwest::StatusCode::from_u16(status).unwrap();
+        match api_error(status, json) {
+            RedditError::Unavailable(u) => assert_eq!(u, reason),
+            other => panic!("expected unavailable, found {:?}", other),
+        }
+    }
+
+    fn check_canonical_embed(src: &str, expected: Option<&str>) {
+        let src = url::Url::parse(src).unwrap();
+        assert_eq!(canonical_embed_url(&src).as_deref(), expected);
     }
 
     fn check_parse_and_crosspost(json: &str, xpost: &str) {
@@ -225,6 +972,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn make_url_from_permalink_with_query() {
+        check_url_jsonify(
+            "https://www.reddit.com/r/gifs/comments/9zgyim/title/c1/?context=3&utm_source=share",
+            "https://www.reddit.com/r/gifs/comments/9zgyim/title/c1/.json?context=3&utm_source=share",
+        )
+    }
+
+    #[test]
+    fn make_url_from_permalink_with_trailing_slash() {
+        check_url_jsonify(
+            "https://www.reddit.com/r/gifs/comments/9zgyim/heres_what_a_rocket_launch_looks_like_from_the/",
+            "https://www.reddit.com/r/gifs/comments/9zgyim/heres_what_a_rocket_launch_looks_like_from_the/.json",
+        )
+    }
+
     #[test]
     fn make_url_from_short_reddit_url() {
         check_url_jsonify(
@@ -280,6 +1043,11 @@ mod tests {
         check_parse_and_subreddit(TEXT, "Jokes");
     }
 
+    #[test]
+    fn parse_too_many_requests() {
+        assert!(serde_json::from_str::<ApiResponse>(TOO_MANY_REQUESTS).is_err());
+    }
+
     #[test]
     fn parse_vreddit() {
         check_parse_and_subreddit(VREDDIT, "gifs");
@@ -290,6 +1058,11 @@ mod tests {
         check_parse_and_subreddit(VREDDIT_PREVIEW, "SpaceGifs");
     }
 
+    #[test]
+    fn parse_youtube() {
+        check_parse_and_subreddit(YOUTUBE, "videos");
+    }
+
     #[test]
     fn scan_media_empty_text() {
         check_parse_and_media(EMPTY_TEXT, None);
@@ -303,9 +1076,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn scan_media_gfycat_provider() {
+        check_parse_and_provider(GFYCAT, "Gfycat");
+    }
+
+    #[test]
+    fn scan_media_youtube() {
+        check_parse_and_media(YOUTUBE, Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+    }
+
+    #[test]
+    fn scan_media_youtube_provider() {
+        check_parse_and_provider(YOUTUBE, "YouTube");
+    }
+
+    #[test]
+    fn canonical_twitch_clip() {
+        check_canonical_embed(
+            "https://clips.twitch.tv/embed?clip=CuteBrainyEelPeteZaroll&parent=www.example.com",
+            Some("https://clips.twitch.tv/CuteBrainyEelPeteZaroll"),
+        );
+    }
+
+    #[test]
+    fn canonical_streamable_through_embedly() {
+        check_canonical_embed(
+            "https://cdn.embedly.com/widgets/media.html?src=https%3A%2F%2Fstreamable.com%2Fo%2Fmoo0cs&display_name=Streamable",
+            Some("https://streamable.com/moo0cs"),
+        );
+    }
+
+    #[test]
+    fn canonical_unknown_embed() {
+        check_canonical_embed(
+            "https://cdn.embedly.com/widgets/media.html?src=https%3A%2F%2Fgfycat.com%2Fifr%2Fdistincthonestiaerismetalmark",
+            None,
+        );
+    }
+
     #[test]
     fn scan_media_imgur() {
-        check_parse_and_media(IMGUR, Some("http://i.imgur.com/wSME5Xy.gif"));
+        check_parse_and_media(
+            IMGUR,
+            Some("https://external-preview.redd.it/05f3rBh7qZENQjIgMYhEcNBEBid7OEJi9LTvYQeh7S8.gif?format=mp4&s=32a1a867f0725667488bd01a672914b0a7ad2d69"),
+        );
     }
 
     #[test]
@@ -315,12 +1130,32 @@ mod tests {
 
     #[test]
     fn scan_media_jgifs() {
-        check_parse_and_media(JGIFS, Some("https://j.gifs.com/m8bLeJ.gif"));
+        check_parse_and_media(
+            JGIFS,
+            Some("https://external-preview.redd.it/rPux8yb1ZBzjhmVvQdsRq5xeuHEyvxexMYAwvtSSTyA.gif?format=mp4&s=7c8b8cc6444e33bfe7ae7e71840ec8daf9f1440f"),
+        );
     }
 
     #[test]
     fn scan_media_text() {
-        check_parse_and_media(TEXT, None);
+        check_parse_and_media_source(
+            TEXT,
+            Some(PostMediaSource::Text(Quote {
+                title: "How do you get Canadian bacon to stop curling in the pan?".to_string(),
+                text: "You take away the broom.".to_string(),
+            })),
+        );
+    }
+
+    #[test]
+    fn scan_media_self_post() {
+        check_parse_and_media_list(
+            SELF_POST_MEDIA,
+            vec![
+                "https://preview.redd.it/b7x2kq9ndfg81.jpg?width=1080&format=pjpg&auto=webp&s=4f2c",
+                "https://www.youtube.com/watch?v=Xq2zW8bLk1c",
+            ],
+        );
     }
 
     #[test]
@@ -328,11 +1163,225 @@ mod tests {
         check_parse_and_media(VREDDIT, Some("https://v.redd.it/6zyfsfjjlxz11/DASH_4_8_M?"));
     }
 
+    #[test]
+    fn scan_media_secure_media() {
+        check_parse_and_media(
+            SECURE_MEDIA,
+            Some("https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?"),
+        );
+    }
+
+    #[test]
+    fn scan_media_reddit_video_preview() {
+        check_parse_and_media(
+            REDDIT_VIDEO_PREVIEW,
+            Some("https://v.redd.it/9yk2xq3c1dd81/DASH_720.mp4?"),
+        );
+    }
+
+    #[test]
+    fn scan_media_preview_variants() {
+        check_parse_and_media(
+            PREVIEW_VARIANTS,
+            Some("https://external-preview.redd.it/a1Yq2Wv3xF6P4KZb0qTzYbEwGq7Vb9ZpXlW2Yk3fTsE.gif?format=mp4&s=3d9f70"),
+        );
+    }
+
+    #[test]
+    fn scan_media_preview_variants_beat_direct_url() {
+        // links straight to a gif, which reddit also has as an mp4
+        check_parse_and_media(
+            DIRECT_GIF_VARIANTS,
+            Some("https://external-preview.redd.it/a1Yq2Wv3xF6P4KZb0qTzYbEwGq7Vb9ZpXlW2Yk3fTsE.gif?format=mp4&s=3d9f70"),
+        );
+    }
+
+    #[test]
+    fn scan_media_deleted() {
+        check_parse_and_unavailable(DELETED, Unavailable::Deleted);
+    }
+
+    #[test]
+    fn scan_media_removed() {
+        check_parse_and_unavailable(REMOVED, Unavailable::Removed("moderator".to_string()));
+    }
+
+    #[test]
+    fn scan_media_removed_keeps_reddit_video() {
+        check_parse_and_media(
+            VREDDIT_SHORT_URL,
+            Some("https://v.redd.it/yub5uok42jq81/DASH_360.mp4?"),
+        );
+    }
+
+    #[test]
+    fn comment_id_from_permalink() {
+        assert_eq!(
+            comment_id_from_url("https://www.reddit.com/r/Jokes/comments/skvln3/how_do_you_get_canadian_bacon_to_stop_curling_in/hvnf2k1/?context=3").as_deref(),
+            Some("hvnf2k1")
+        );
+    }
+
+    #[test]
+    fn comment_id_from_short_permalink() {
+        assert_eq!(
+            comment_id_from_url("https://www.reddit.com/r/Jokes/comments/skvln3/comment/hvnf2k1/")
+                .as_deref(),
+            Some("hvnf2k1")
+        );
+    }
+
+    #[test]
+    fn no_comment_id_from_post() {
+        assert_eq!(
+            comment_id_from_url("https://www.reddit.com/r/Jokes/comments/skvln3/how_do_you_get_canadian_bacon_to_stop_curling_in/"),
+            None
+        );
+    }
+
+    #[test]
+    fn empty_listing_is_an_error() {
+        let resp: ApiResponse = serde_json::from_str("[]").unwrap();
+        assert!(get_post_from_response(&resp).is_err());
+        let resp: ApiResponse =
+            serde_json::from_str(r#"[{"kind": "Listing", "data": {"children": []}}]"#).unwrap();
+        assert!(get_post_from_response(&resp).is_err());
+    }
+
+    #[test]
+    fn scan_comment_image() {
+        check_comment_media(
+            COMMENT_IMAGE,
+            "hvnf2k1",
+            Some(PostMediaSource::Media(vec![Media::new(
+                "https://preview.redd.it/q3xv9k1b2fg81.jpg?width=640&format=pjpg&auto=webp&s=9a0e",
+            )
+            .with_dimensions(Some(640), Some(640))])),
+        );
+    }
+
+    #[test]
+    fn scan_comment_link() {
+        check_comment_media(
+            COMMENT_LINK,
+            "hvng7x3",
+            Some(PostMediaSource::Media(vec![Media::new(
+                "https://i.imgur.com/Vb3sQzR.jpg",
+            )])),
+        );
+    }
+
+    #[test]
+    fn scan_comment_skips_links_that_arent_media() {
+        let comment: CommentInfo = serde_json::from_str(
+            r#"{"id": "hvnh9k4", "body": "see https://en.wikipedia.org/wiki/Falcon_9 for more"}"#,
+        )
+        .unwrap();
+        assert_eq!(scan_comment_for_media(&comment).unwrap(), None);
+
+        let comment: CommentInfo = serde_json::from_str(
+            r#"{"id": "hvnh9k5", "body": "https://en.wikipedia.org/wiki/Falcon_9 has it, closer: https://i.imgur.com/Vb3sQzR.jpg"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            scan_comment_for_media(&comment).unwrap(),
+            Some(PostMediaSource::Media(vec![Media::new(
+                "https://i.imgur.com/Vb3sQzR.jpg"
+            )]))
+        );
+    }
+
+    #[test]
+    fn scan_comment_link_to_post() {
+        let comment: CommentInfo = serde_json::from_str(
+            r#"{"id": "hvnh1a2", "body": "reposted from https://www.reddit.com/r/gifs/comments/9zgyim/heres_what_a_rocket_launch_looks_like_from_the/"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            scan_comment_for_media(&comment).unwrap(),
+            Some(PostMediaSource::CrossPost(
+                "https://www.reddit.com/r/gifs/comments/9zgyim/heres_what_a_rocket_launch_looks_like_from_the/".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn minimize_keeps_targeted_comment() {
+        let resp: ApiResponse = serde_json::from_str(COMMENT_IMAGE).unwrap();
+        let min = resp.clone().minimize(Some("hvnf2k1"));
+        assert_eq!(min.data.len(), 2);
+        assert!(get_comment_from_response(&min, "hvnf2k1").is_some());
+        assert_eq!(
+            scan_for_media(get_post_from_response(&min).unwrap()).unwrap(),
+            scan_for_media(get_post_from_response(&resp).unwrap()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn minimize_drops_comments() {
+        let resp: ApiResponse = serde_json::from_str(TEXT).unwrap();
+        let min = resp.minimize(None);
+        assert_eq!(min.data.len(), 1);
+        let json = serde_json::to_string(&min).unwrap();
+        assert!(json.len() < TEXT.len() / 10);
+    }
+
+    #[test]
+    fn reddit_links_in_chat_line() {
+        assert_eq!(
+            reddit_links_in_text(
+                "[12:01] bob: lol https://www.reddit.com/r/gifs/comments/9zgyim/rocket/ and https://example.com/x https://v.redd.it/dkczbt15n2r71"
+            ),
+            vec![
+                "https://www.reddit.com/r/gifs/comments/9zgyim/rocket/",
+                "https://v.redd.it/dkczbt15n2r71"
+            ]
+        );
+    }
+
+    #[test]
+    fn api_error_private() {
+        check_api_error_unavailable(403, PRIVATE, Unavailable::Private);
+    }
+
+    #[test]
+    fn api_error_quarantined() {
+        check_api_error_unavailable(403, QUARANTINED, Unavailable::Quarantined);
+    }
+
+    #[test]
+    fn api_error_too_many_requests() {
+        let status = reqwest::StatusCode::TOO_MANY_REQUESTS;
+        assert!(matches!(
+            api_error(status, TOO_MANY_REQUESTS),
+            RedditError::TooManyRequests
+        ));
+    }
+
     #[test]
     fn scan_media_cross_post() {
         check_parse_and_crosspost(CROSS_POST, "https://v.redd.it/dkczbt15n2r71");
     }
 
+    #[test]
+    fn scan_media_reddit_video_dimensions() {
+        match scan_fixture(VREDDIT_PREVIEW) {
+            Some(PostMediaSource::Media(m)) => {
+                assert!(m[0].width.is_some() && m[0].height.is_some())
+            }
+            other => panic!("expected media, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn post_summary() {
+        let resp = serde_json::from_str::<ApiResponse>(VREDDIT_PREVIEW).unwrap();
+        let summary = get_post_from_response(&resp).unwrap().summary();
+        assert_eq!(summary.id, "saj6w9");
+        assert_eq!(summary.subreddit, "SpaceGifs");
+        assert!(!summary.nsfw);
+    }
+
     #[test]
     fn scan_media_vreddit_preview() {
         check_parse_and_media(
@@ -340,4 +1389,30 @@ mod tests {
             Some("https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?"),
         );
     }
+
+    #[tokio::test]
+    async fn subreddit_listing() {
+        use crate::transport::{Cassette, ReplayTransport};
+        let cassette: Cassette =
+            serde_json::from_str(include_str!("../sample_responses/cassettes/listing.json"))
+                .unwrap();
+        let client = RedditClient::with_transport(Arc::new(ReplayTransport::new(cassette)))
+            .with_rate_limiter(RateLimiter::unlimited());
+        let posts = client
+            .subreddit_posts("SpaceGifs", ListingSort::New)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = posts.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["s9zz01", "saj6w9", "sa1b2c"]);
+        assert!(posts[0].stickied);
+        assert_eq!(posts[1].score, 154);
+        assert_eq!(
+            posts[1].url,
+            "https://www.reddit.com/r/SpaceGifs/comments/saj6w9/the_edgeon_galaxy_ngc_5866_captured_by_hubble/"
+        );
+        assert!(client
+            .subreddit_posts("../../api", ListingSort::New)
+            .await
+            .is_err());
+    }
 }
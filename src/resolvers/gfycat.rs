@@ -0,0 +1,96 @@
+use super::{host_matches, is_direct_media, path_segments, MediaResolver};
+use crate::media::Media;
+use crate::transport::{HttpRequest, HttpTransport};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use url::Url;
+
+const API_URL: &str = "https://api.gfycat.com/v1/gfycats/";
+
+pub struct GfycatResolver;
+
+#[derive(Debug, Deserialize)]
+struct GfycatResponse {
+    #[serde(rename = "gfyItem")]
+    gfy_item: GfyItem,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GfyItem {
+    mp4_url: Option<String>,
+    gif_url: Option<String>,
+}
+
+#[async_trait]
+impl MediaResolver for GfycatResolver {
+    fn name(&self) -> &'static str {
+        "gfycat"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_matches(url, "gfycat.com") && !is_direct_media(url)
+    }
+
+    async fn resolve(&self, http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>> {
+        let id = gfycat_id(url).ok_or_else(|| anyhow::anyhow!("no gfycat id in {}", url))?;
+        let resp = http
+            .send(HttpRequest::get(format!("{}{}", API_URL, id)))
+            .await?
+            .error_for_status()?
+            .json::<GfycatResponse>()?;
+
+        let item = resp.gfy_item;
+        Ok(item
+            .mp4_url
+            .or(item.gif_url)
+            .map(Media::new)
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Pull the gfy name out of urls like `gfycat.com/Name`,
+/// `gfycat.com/ifr/Name` or `gfycat.com/gifs/detail/Name-some-tags`
+fn gfycat_id(url: &Url) -> Option<&str> {
+    let name = path_segments(url).into_iter().last()?;
+    let name = name.split('-').next()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_id(url: &str, expected: Option<&str>) {
+        let url = Url::parse(url).unwrap();
+        assert_eq!(gfycat_id(&url), expected);
+    }
+
+    #[test]
+    fn id_from_page() {
+        check_id(
+            "https://gfycat.com/DistinctHonestIaerismetalmark",
+            Some("DistinctHonestIaerismetalmark"),
+        );
+    }
+
+    #[test]
+    fn id_from_tagged_detail_page() {
+        check_id(
+            "https://gfycat.com/gifs/detail/distincthonestiaerismetalmark-mars-space/",
+            Some("distincthonestiaerismetalmark"),
+        );
+    }
+
+    #[test]
+    fn direct_links_are_left_alone() {
+        let url = Url::parse("https://thumbs.gfycat.com/Distinct-mobile.mp4").unwrap();
+        assert!(!GfycatResolver.matches(&url));
+    }
+}
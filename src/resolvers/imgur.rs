@@ -0,0 +1,262 @@
+use super::{host_matches, path_segments, MediaResolver};
+use crate::media::Media;
+use crate::transport::{HttpRequest, HttpTransport};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use url::Url;
+
+const API_URL: &str = "https://api.imgur.com/3";
+
+/// `.gifv` links are rewritten to their mp4 without any network access.
+///
+/// Image pages, albums and galleries need the imgur api, so they are only
+/// handled when a client id is configured.
+pub struct ImgurResolver {
+    client_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiData<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgurImage {
+    link: String,
+    mp4: Option<String>,
+    #[serde(default)]
+    animated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgurGallery {
+    #[serde(default)]
+    is_album: bool,
+    #[serde(default)]
+    images: Vec<ImgurImage>,
+    link: String,
+    mp4: Option<String>,
+    #[serde(default)]
+    animated: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum ImgurLink<'a> {
+    Gifv(&'a str),
+    Image(&'a str),
+    Album(&'a str),
+    Gallery(&'a str),
+}
+
+impl ImgurResolver {
+    pub fn new(client_id: Option<String>) -> ImgurResolver {
+        ImgurResolver {
+            client_id: client_id.filter(|id| !id.is_empty()),
+        }
+    }
+
+    /// Read the client id from `IMGUR_CLIENT_ID`
+    pub fn from_env() -> ImgurResolver {
+        ImgurResolver::new(std::env::var("IMGUR_CLIENT_ID").ok())
+    }
+
+    async fn api_get<T: DeserializeOwned>(
+        &self,
+        http: &dyn HttpTransport,
+        path: &str,
+    ) -> Result<T> {
+        let client_id = self
+            .client_id
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("imgur api requires a client id"))?;
+        let req = HttpRequest::get(format!("{}/{}", API_URL, path))
+            .header("Authorization", format!("Client-ID {}", client_id));
+        let resp = http
+            .send(req)
+            .await?
+            .error_for_status()?
+            .json::<ApiData<T>>()?;
+        Ok(resp.data)
+    }
+}
+
+#[async_trait]
+impl MediaResolver for ImgurResolver {
+    fn name(&self) -> &'static str {
+        "imgur"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        match imgur_link(url) {
+            Some(ImgurLink::Gifv(_)) => true,
+            Some(_) => self.client_id.is_some(),
+            None => false,
+        }
+    }
+
+    async fn resolve(&self, http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>> {
+        let link = imgur_link(url).ok_or_else(|| anyhow::anyhow!("not an imgur link: {}", url))?;
+        let media = match link {
+            ImgurLink::Gifv(id) => vec![Media::new(format!("https://i.imgur.com/{}.mp4", id))],
+            ImgurLink::Image(id) => {
+                let image: ImgurImage = self.api_get(http, &format!("image/{}", id)).await?;
+                vec![image_media(image)]
+            }
+            ImgurLink::Album(id) => {
+                let images: Vec<ImgurImage> =
+                    self.api_get(http, &format!("album/{}/images", id)).await?;
+                images.into_iter().map(image_media).collect()
+            }
+            ImgurLink::Gallery(id) => {
+                let gallery: ImgurGallery = self.api_get(http, &format!("gallery/{}", id)).await?;
+                gallery_media(gallery)
+            }
+        };
+        Ok(media)
+    }
+}
+
+fn image_media(image: ImgurImage) -> Media {
+    let url = match image.mp4 {
+        Some(mp4) if image.animated => mp4,
+        _ => image.link,
+    };
+    Media::new(url)
+}
+
+fn gallery_media(gallery: ImgurGallery) -> Vec<Media> {
+    if gallery.is_album {
+        gallery.images.into_iter().map(image_media).collect()
+    } else {
+        vec![image_media(ImgurImage {
+            link: gallery.link,
+            mp4: gallery.mp4,
+            animated: gallery.animated,
+        })]
+    }
+}
+
+/// Classify an imgur url, direct links other than `.gifv` are left alone
+fn imgur_link(url: &Url) -> Option<ImgurLink<'_>> {
+    if !host_matches(url, "imgur.com") {
+        return None;
+    }
+
+    let segments = path_segments(url);
+    if url.host_str() == Some("i.imgur.com") {
+        return match segments.as_slice() {
+            [file] => file.strip_suffix(".gifv").map(ImgurLink::Gifv),
+            _ => None,
+        };
+    }
+
+    match segments.as_slice() {
+        ["a", id] => Some(ImgurLink::Album(slug_id(id))),
+        ["gallery" | "t", .., id] => Some(ImgurLink::Gallery(slug_id(id))),
+        [id] if id.ends_with(".gifv") => id.strip_suffix(".gifv").map(ImgurLink::Gifv),
+        [id] if !id.contains('.') => Some(ImgurLink::Image(id)),
+        _ => None,
+    }
+}
+
+/// Newer imgur links prefix the id with a title, `a/some-title-<id>`
+fn slug_id(segment: &str) -> &str {
+    segment.rsplit('-').next().unwrap_or(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolvers::ResolverChain;
+
+    fn check_link(url: &str, expected: Option<ImgurLink<'_>>) {
+        let url = Url::parse(url).unwrap();
+        assert_eq!(imgur_link(&url), expected);
+    }
+
+    #[test]
+    fn direct_gif_is_left_alone() {
+        check_link("http://i.imgur.com/wSME5Xy.gif", None);
+    }
+
+    #[test]
+    fn gifv_link() {
+        check_link(
+            "https://i.imgur.com/wSME5Xy.gifv",
+            Some(ImgurLink::Gifv("wSME5Xy")),
+        );
+    }
+
+    #[test]
+    fn image_page() {
+        check_link(
+            "https://imgur.com/wSME5Xy",
+            Some(ImgurLink::Image("wSME5Xy")),
+        );
+    }
+
+    #[test]
+    fn album_page() {
+        check_link("https://imgur.com/a/Ab3De", Some(ImgurLink::Album("Ab3De")));
+    }
+
+    #[test]
+    fn titled_album_page() {
+        check_link(
+            "https://imgur.com/a/falcon-9-landing-Ab3De",
+            Some(ImgurLink::Album("Ab3De")),
+        );
+    }
+
+    #[test]
+    fn gallery_page() {
+        check_link(
+            "https://imgur.com/gallery/Ab3De",
+            Some(ImgurLink::Gallery("Ab3De")),
+        );
+    }
+
+    #[test]
+    fn pages_need_client_id() {
+        let url = Url::parse("https://imgur.com/a/Ab3De").unwrap();
+        assert!(!ImgurResolver::new(None).matches(&url));
+        assert!(ImgurResolver::new(Some("abc".to_string())).matches(&url));
+    }
+
+    #[test]
+    fn gifv_rewritten_to_mp4() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let url = "https://i.imgur.com/wSME5Xy.gifv";
+        let media = rt.block_on(
+            ResolverChain::new()
+                .with_resolver(ImgurResolver::new(None))
+                .resolve(Media::new(url)),
+        );
+        assert_eq!(
+            media,
+            vec![Media::new("https://i.imgur.com/wSME5Xy.mp4").with_provider("imgur")]
+        );
+    }
+
+    #[test]
+    fn album_expands_animated_images() {
+        let gallery: ImgurGallery = serde_json::from_str(
+            r#"{
+                "is_album": true,
+                "link": "https://imgur.com/a/Ab3De",
+                "images": [
+                    {"link": "https://i.imgur.com/one.gif", "mp4": "https://i.imgur.com/one.mp4", "animated": true},
+                    {"link": "https://i.imgur.com/two.jpg", "animated": false}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let urls: Vec<String> = gallery_media(gallery).into_iter().map(|m| m.url).collect();
+        assert_eq!(
+            urls,
+            vec!["https://i.imgur.com/one.mp4", "https://i.imgur.com/two.jpg"]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Resolvers turn the page url of a media host (gfycat, redgifs, ...) into
+//! direct links to the media, so discord can embed them.
+//!
+//! Adding a new host means writing a `MediaResolver` and registering it in
+//! `ResolverChain::default`, the reddit side does not need to know about it.
+use crate::media::{host_matches, is_direct_media, Media};
+use crate::transport::{HttpTransport, ReqwestTransport};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use url::Url;
+
+mod gfycat;
+mod imgur;
+mod redgifs;
+mod streamable;
+
+pub use gfycat::GfycatResolver;
+pub use imgur::ImgurResolver;
+pub use redgifs::RedgifsResolver;
+pub use streamable::StreamableResolver;
+
+#[async_trait]
+pub trait MediaResolver: Send + Sync {
+    /// Short name of the provider, used for logging
+    fn name(&self) -> &'static str;
+
+    /// Check if this resolver knows how to handle the url
+    fn matches(&self, url: &Url) -> bool;
+
+    /// Turn the url into one or more direct media links
+    async fn resolve(&self, http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>>;
+}
+
+pub struct ResolverChain {
+    transport: Arc<dyn HttpTransport>,
+    resolvers: Vec<Box<dyn MediaResolver>>,
+}
+
+impl Default for ResolverChain {
+    fn default() -> Self {
+        ResolverChain::new()
+            .with_resolver(GfycatResolver)
+            .with_resolver(ImgurResolver::from_env())
+            .with_resolver(RedgifsResolver::default())
+            .with_resolver(StreamableResolver)
+    }
+}
+
+impl ResolverChain {
+    /// A chain without any resolvers, media is passed through untouched
+    pub fn new() -> ResolverChain {
+        ResolverChain {
+            transport: Arc::new(ReqwestTransport::default()),
+            resolvers: Vec::new(),
+        }
+    }
+
+    /// Send the resolvers' requests through something other than the network
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> ResolverChain {
+        self.transport = transport;
+        self
+    }
+
+    pub fn with_resolver<R: MediaResolver + 'static>(mut self, resolver: R) -> ResolverChain {
+        self.register(resolver);
+        self
+    }
+
+    pub fn register<R: MediaResolver + 'static>(&mut self, resolver: R) {
+        self.resolvers.push(Box::new(resolver))
+    }
+
+    /// Resolve each of the media, keeping their order
+    pub async fn resolve_all(&self, media: Vec<Media>) -> Vec<Media> {
+        self.resolve_all_traced(media, &mut Vec::new()).await
+    }
+
+    /// Like `resolve_all`, noting what each resolver did in `trace`
+    pub async fn resolve_all_traced(
+        &self,
+        media: Vec<Media>,
+        trace: &mut Vec<String>,
+    ) -> Vec<Media> {
+        let mut resolved = Vec::with_capacity(media.len());
+        for m in media {
+            resolved.extend(self.resolve_traced(m, trace).await);
+        }
+        resolved
+    }
+
+    /// Run the media through the first resolver that claims it.
+    ///
+    /// Resolution is best effort, if the provider can not be reached
+    /// the original media is returned.
+    pub async fn resolve(&self, media: Media) -> Vec<Media> {
+        self.resolve_traced(media, &mut Vec::new()).await
+    }
+
+    async fn resolve_traced(&self, media: Media, trace: &mut Vec<String>) -> Vec<Media> {
+        let url = match Url::parse(&media.url) {
+            Ok(u) => u,
+            Err(e) => {
+                log::debug!("not resolving unparsable url {:?}: {}", media.url, e);
+                return vec![media];
+            }
+        };
+
+        let resolver = match self.resolvers.iter().find(|r| r.matches(&url)) {
+            Some(r) => r,
+            None => return vec![media],
+        };
+
+        log::debug!("resolving {} with {}", url, resolver.name());
+        match resolver.resolve(self.transport.as_ref(), &url).await {
+            Ok(resolved) if !resolved.is_empty() => {
+                trace.push(format!("resolved {} with {}", url, resolver.name()));
+                resolved
+                    .into_iter()
+                    .map(|m| match m.provider {
+                        Some(_) => m,
+                        None => m.with_provider(resolver.name()),
+                    })
+                    .collect()
+            }
+            Ok(_) => {
+                log::warn!("{} found no media for {}", resolver.name(), url);
+                trace.push(format!("{} found no media for {}", resolver.name(), url));
+                vec![media]
+            }
+            Err(e) => {
+                log::warn!("{} could not resolve {}: {}", resolver.name(), url, e);
+                trace.push(format!(
+                    "{} could not resolve {}: {}",
+                    resolver.name(),
+                    url,
+                    e
+                ));
+                vec![media]
+            }
+        }
+    }
+}
+
+/// The path segments of the url, skipping empty ones
+fn path_segments(url: &Url) -> Vec<&str> {
+    url.path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rewrite;
+
+    #[async_trait]
+    impl MediaResolver for Rewrite {
+        fn name(&self) -> &'static str {
+            "rewrite"
+        }
+
+        fn matches(&self, url: &Url) -> bool {
+            host_matches(url, "example.com")
+        }
+
+        async fn resolve(&self, _http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>> {
+            Ok(vec![Media::new(format!("{}.mp4", url))])
+        }
+    }
+
+    fn resolve(chain: &ResolverChain, url: &str) -> Vec<String> {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(chain.resolve(Media::new(url)))
+            .into_iter()
+            .map(|m| m.url)
+            .collect()
+    }
+
+    #[test]
+    fn chain_uses_matching_resolver() {
+        let chain = ResolverChain::new().with_resolver(Rewrite);
+        assert_eq!(
+            resolve(&chain, "https://www.example.com/abc"),
+            vec!["https://www.example.com/abc.mp4"]
+        );
+    }
+
+    #[test]
+    fn chain_passes_through_unmatched() {
+        let chain = ResolverChain::new().with_resolver(Rewrite);
+        assert_eq!(
+            resolve(&chain, "https://i.redd.it/kaopcso5hqw61.jpg"),
+            vec!["https://i.redd.it/kaopcso5hqw61.jpg"]
+        );
+    }
+
+    #[test]
+    fn host_matches_subdomains() {
+        let url = Url::parse("https://thumbs.gfycat.com/Foo.mp4").unwrap();
+        assert!(host_matches(&url, "gfycat.com"));
+        assert!(!host_matches(&url, "fycat.com"));
+    }
+}
@@ -0,0 +1,133 @@
+use super::{host_matches, is_direct_media, path_segments, MediaResolver};
+use crate::media::Media;
+use crate::transport::{HttpRequest, HttpTransport};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use url::Url;
+
+const AUTH_URL: &str = "https://api.redgifs.com/v2/auth/temporary";
+const API_URL: &str = "https://api.redgifs.com/v2/gifs/";
+
+/// Redgifs requires a bearer token even for public gifs, a temporary one
+/// is fetched on first use and refreshed when it is rejected.
+#[derive(Default)]
+pub struct RedgifsResolver {
+    token: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GifResponse {
+    gif: Gif,
+}
+
+#[derive(Debug, Deserialize)]
+struct Gif {
+    urls: GifUrls,
+}
+
+#[derive(Debug, Deserialize)]
+struct GifUrls {
+    hd: Option<String>,
+    sd: Option<String>,
+}
+
+impl RedgifsResolver {
+    async fn token(&self, http: &dyn HttpTransport) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(t) = token.as_ref() {
+            return Ok(t.clone());
+        }
+        let resp = http
+            .send(HttpRequest::get(AUTH_URL))
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()?;
+        *token = Some(resp.token.clone());
+        Ok(resp.token)
+    }
+
+    async fn fetch_gif(&self, http: &dyn HttpTransport, id: &str) -> Result<Option<GifResponse>> {
+        let token = self.token(http).await?;
+        let resp = http
+            .send(HttpRequest::get(format!("{}{}", API_URL, id)).bearer_auth(token))
+            .await?;
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            *self.token.lock().await = None;
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<GifResponse>()?))
+    }
+}
+
+#[async_trait]
+impl MediaResolver for RedgifsResolver {
+    fn name(&self) -> &'static str {
+        "redgifs"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_matches(url, "redgifs.com") && !is_direct_media(url)
+    }
+
+    async fn resolve(&self, http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>> {
+        let id = redgifs_id(url).ok_or_else(|| anyhow::anyhow!("no redgifs id in {}", url))?;
+
+        let resp = match self.fetch_gif(http, &id).await? {
+            Some(r) => r,
+            // the token expired, try once more with a fresh one
+            None => self
+                .fetch_gif(http, &id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("redgifs rejected a fresh token"))?,
+        };
+
+        let urls = resp.gif.urls;
+        Ok(urls.hd.or(urls.sd).map(Media::new).into_iter().collect())
+    }
+}
+
+/// Pull the (lowercase) id out of `redgifs.com/watch/<id>` or `redgifs.com/ifr/<id>`
+fn redgifs_id(url: &Url) -> Option<String> {
+    match path_segments(url).as_slice() {
+        ["watch" | "ifr", id, ..] => Some(id.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_id(url: &str, expected: Option<&str>) {
+        let url = Url::parse(url).unwrap();
+        assert_eq!(redgifs_id(&url).as_deref(), expected);
+    }
+
+    #[test]
+    fn id_from_watch_page() {
+        check_id(
+            "https://www.redgifs.com/watch/SomeAnimatedName",
+            Some("someanimatedname"),
+        );
+    }
+
+    #[test]
+    fn id_from_embed() {
+        check_id(
+            "https://v3.redgifs.com/ifr/someanimatedname",
+            Some("someanimatedname"),
+        );
+    }
+
+    #[test]
+    fn no_id_from_profile() {
+        check_id("https://www.redgifs.com/users/someone", None);
+    }
+}
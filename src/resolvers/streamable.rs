@@ -0,0 +1,100 @@
+use super::{host_matches, is_direct_media, path_segments, MediaResolver};
+use crate::media::Media;
+use crate::transport::{HttpRequest, HttpTransport};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::Url;
+
+const API_URL: &str = "https://api.streamable.com/videos/";
+
+pub struct StreamableResolver;
+
+#[derive(Debug, Deserialize)]
+struct StreamableResponse {
+    files: HashMap<String, StreamableFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamableFile {
+    url: Option<String>,
+}
+
+#[async_trait]
+impl MediaResolver for StreamableResolver {
+    fn name(&self) -> &'static str {
+        "streamable"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_matches(url, "streamable.com") && !is_direct_media(url)
+    }
+
+    async fn resolve(&self, http: &dyn HttpTransport, url: &Url) -> Result<Vec<Media>> {
+        let code =
+            streamable_code(url).ok_or_else(|| anyhow::anyhow!("no streamable id in {}", url))?;
+        let resp = http
+            .send(HttpRequest::get(format!("{}{}", API_URL, code)))
+            .await?
+            .error_for_status()?
+            .json::<StreamableResponse>()?;
+
+        let file = ["mp4", "mp4-mobile"]
+            .iter()
+            .filter_map(|k| resp.files.get(*k))
+            .find_map(|f| f.url.as_deref());
+
+        Ok(file
+            .map(|u| Media::new(absolute_url(u)))
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Pull the shortcode out of `streamable.com/<code>` or the
+/// embed forms `streamable.com/e/<code>` and `streamable.com/o/<code>`
+fn streamable_code(url: &Url) -> Option<&str> {
+    match path_segments(url).as_slice() {
+        [code] => Some(code),
+        ["e" | "o" | "s", code, ..] => Some(code),
+        _ => None,
+    }
+}
+
+/// The api hands out protocol relative links
+fn absolute_url(u: &str) -> String {
+    if u.starts_with("//") {
+        format!("https:{}", u)
+    } else {
+        u.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_code(url: &str, expected: Option<&str>) {
+        let url = Url::parse(url).unwrap();
+        assert_eq!(streamable_code(&url), expected);
+    }
+
+    #[test]
+    fn code_from_page() {
+        check_code("https://streamable.com/moo0cs", Some("moo0cs"));
+    }
+
+    #[test]
+    fn code_from_embed() {
+        check_code("https://streamable.com/e/moo0cs", Some("moo0cs"));
+    }
+
+    #[test]
+    fn protocol_relative_urls() {
+        assert_eq!(
+            absolute_url("//cdn-cf-east.streamable.com/video/mp4/moo0cs.mp4"),
+            "https://cdn-cf-east.streamable.com/video/mp4/moo0cs.mp4"
+        );
+    }
+}
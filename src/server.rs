@@ -0,0 +1,366 @@
+//! An http api over the extractor, for tools that aren't discord.
+//!
+//! * `GET /extract?url=<link>` answers with the json record of the extraction
+//! * `GET /media?url=<link>` redirects to the first media url
+//! * `GET /health` answers `ok`, without needing credentials
+//!
+//! Requests need an api key (the `x-api-key` header or a `key` query
+//! parameter) or basic auth, when either is configured.
+//!
+//! Only reddit links are looked up, so the server can't be pointed at
+//! anything else on the network.
+use crate::media::host_matches;
+use crate::media_extraction::ErrorKind;
+use crate::record::{Record, Status};
+use crate::{Extraction, Extractor, MediaOutcome};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    pub api_keys: Vec<String>,
+    /// `user:password`
+    pub basic: Option<String>,
+}
+
+impl Auth {
+    pub fn is_open(&self) -> bool {
+        self.api_keys.is_empty() && self.basic.is_none()
+    }
+
+    fn allows(&self, req: &Request<Body>, query: &HashMap<String, String>) -> bool {
+        if self.is_open() {
+            return true;
+        }
+        let key = req
+            .headers()
+            .get("x-api-key")
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| query.get("key").map(|k| k.as_str()));
+        if let Some(key) = key {
+            if self.api_keys.iter().any(|k| k == key) {
+                return true;
+            }
+        }
+        if let Some(basic) = &self.basic {
+            let expected = format!("Basic {}", base64::encode(basic));
+            let given = req.headers().get(header::AUTHORIZATION);
+            if given.and_then(|v| v.to_str().ok()) == Some(expected.as_str()) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    /// How long an extraction may take before the request gives up
+    pub timeout: Duration,
+    pub auth: Auth,
+}
+
+struct State {
+    extractor: Extractor,
+    timeout: Duration,
+    auth: Auth,
+}
+
+/// Serve the api until the process is stopped
+pub async fn serve(extractor: Extractor, config: ServerConfig) -> anyhow::Result<()> {
+    if config.auth.is_open() {
+        log::warn!("no api key or basic auth configured, anyone can use the server");
+    }
+    let state = Arc::new(State {
+        extractor,
+        timeout: config.timeout,
+        auth: config.auth,
+    });
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, req).await) }
+            }))
+        }
+    });
+    log::info!("listening on http://{}", config.addr);
+    hyper::Server::try_bind(&config.addr)?
+        .serve(make_svc)
+        .await?;
+    Ok(())
+}
+
+async fn handle(state: &State, req: Request<Body>) -> Response<Body> {
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    log::debug!("{} {}", req.method(), req.uri().path());
+
+    if req.method() != Method::GET {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+    }
+    if req.uri().path() == "/health" {
+        return text(StatusCode::OK, "ok");
+    }
+    if !state.auth.allows(&req, &query) {
+        let mut resp = text(StatusCode::UNAUTHORIZED, "unauthorized");
+        if state.auth.basic.is_some() {
+            resp.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Basic realm=\"bananagrabber\""),
+            );
+        }
+        return resp;
+    }
+
+    let path = req.uri().path();
+    if path != "/extract" && path != "/media" {
+        return text(StatusCode::NOT_FOUND, "not found");
+    }
+    let url = match query.get("url") {
+        Some(u) => u,
+        None => return text(StatusCode::BAD_REQUEST, "missing url parameter"),
+    };
+    if !is_reddit_link(url) {
+        return text(StatusCode::BAD_REQUEST, "not a reddit link");
+    }
+
+    let extraction =
+        match tokio::time::timeout(state.timeout, state.extractor.extract_detailed(url)).await {
+            Ok(e) => e,
+            Err(_) => {
+                log::warn!("timed out extracting {}", url);
+                return text(StatusCode::GATEWAY_TIMEOUT, "timed out");
+            }
+        };
+
+    if path == "/media" {
+        if let Ok(MediaOutcome::Found(media)) = &extraction.outcome {
+            if let Some(m) = media.first() {
+                return redirect(url, &m.url);
+            }
+        }
+    }
+    record_response(path, url, &extraction)
+}
+
+/// Send the client on to the media found for `url`
+fn redirect(url: &str, media_url: &str) -> Response<Body> {
+    // urls from post text aren't always valid header values
+    let location = match header::HeaderValue::from_str(media_url) {
+        Ok(l) => l,
+        Err(_) => {
+            log::warn!("can't redirect to {:?}, found for {}", media_url, url);
+            return text(
+                StatusCode::BAD_GATEWAY,
+                "the media url can't be redirected to",
+            );
+        }
+    };
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Links to reddit posts, on reddit.com or its short link hosts
+fn is_reddit_link(url: &str) -> bool {
+    let url = match url::Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return false,
+    };
+    (url.scheme() == "https" || url.scheme() == "http")
+        && (host_matches(&url, "reddit.com")
+            || url.host_str() == Some("redd.it")
+            || url.host_str() == Some("v.redd.it"))
+}
+
+/// What an error is reported as, upstream responses stay in the log
+fn error_message(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::RateLimited => "reddit is rate limiting us",
+        ErrorKind::HttpStatus => "reddit returned an error",
+        ErrorKind::Network => "could not reach reddit",
+        ErrorKind::Parse => "could not read reddit's response",
+        ErrorKind::Other => "extraction failed",
+    }
+}
+
+/// The record, with a status code that says whether there was media to find
+fn record_response(path: &str, url: &str, extraction: &Extraction) -> Response<Body> {
+    let mut record = Record::new(url, extraction);
+    if let Some(error) = &mut record.error {
+        log::warn!("extracting {} failed: {}", url, error.message);
+        error.message = error_message(error.kind).to_string();
+    }
+    let status = match record.status {
+        Status::Error => StatusCode::BAD_GATEWAY,
+        // `/media` has nothing to redirect to
+        _ if path == "/media" => StatusCode::NOT_FOUND,
+        _ => StatusCode::OK,
+    };
+    let body = serde_json::to_string(&record).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn text(status: StatusCode, msg: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(msg))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolvers::ResolverChain;
+    use crate::transport::{Cassette, ReplayTransport};
+
+    const CROSS_POST: &str = include_str!("../sample_responses/cassettes/cross_post.json");
+    const LINK: &str = "https://www.reddit.com/r/gifs/comments/q0x1pe/the_edgeon_galaxy";
+
+    fn state(auth: Auth) -> State {
+        let cassette: Cassette = serde_json::from_str(CROSS_POST).unwrap();
+        State {
+            extractor: Extractor::default()
+                .with_resolvers(ResolverChain::new())
+                .with_transport(Arc::new(ReplayTransport::new(cassette))),
+            timeout: DEFAULT_TIMEOUT,
+            auth,
+        }
+    }
+
+    fn get(state: &State, uri: &str, headers: &[(&str, &str)]) -> Response<Body> {
+        let mut req = Request::get(uri);
+        for (k, v) in headers {
+            req = req.header(*k, *v);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(handle(state, req))
+    }
+
+    fn with_link(path: &str) -> String {
+        format!(
+            "{}?url={}",
+            path,
+            url::form_urlencoded::byte_serialize(LINK.as_bytes()).collect::<String>()
+        )
+    }
+
+    #[test]
+    fn media_redirects() {
+        let resp = get(&state(Auth::default()), &with_link("/media"), &[]);
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(
+            resp.headers()[header::LOCATION],
+            "https://v.redd.it/u23a45f7pcd81/DASH_720.mp4?"
+        );
+    }
+
+    #[test]
+    fn bad_media_urls_are_not_redirected_to() {
+        let resp = redirect(LINK, "https://i.imgur.com/a.gif\r\nSet-Cookie: x=y");
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn extract_returns_record() {
+        let resp = get(&state(Auth::default()), &with_link("/extract"), &[]);
+        assert_eq!(resp.status(), StatusCode::OK);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let body = rt
+            .block_on(hyper::body::to_bytes(resp.into_body()))
+            .unwrap();
+        let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(record["status"], "found");
+        assert_eq!(record["post"]["id"], "saj6w9");
+    }
+
+    #[test]
+    fn missing_url() {
+        let resp = get(&state(Auth::default()), "/extract", &[]);
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn only_reddit_links() {
+        let state = state(Auth::default());
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "https://reddit.com.example.com/r/gifs/",
+            "file:///etc/passwd",
+        ] {
+            let uri = format!(
+                "/extract?url={}",
+                url::form_urlencoded::byte_serialize(url.as_bytes()).collect::<String>()
+            );
+            assert_eq!(get(&state, &uri, &[]).status(), StatusCode::BAD_REQUEST);
+        }
+        assert!(is_reddit_link(
+            "https://old.reddit.com/r/gifs/comments/q0x1pe/"
+        ));
+        assert!(is_reddit_link("https://v.redd.it/u23a45f7pcd81"));
+    }
+
+    #[test]
+    fn api_key_required() {
+        let auth = Auth {
+            api_keys: vec!["sekrit".to_string()],
+            basic: None,
+        };
+        let state = state(auth);
+        assert_eq!(
+            get(&state, &with_link("/media"), &[]).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get(&state, &with_link("/media"), &[("x-api-key", "sekrit")]).status(),
+            StatusCode::FOUND
+        );
+        assert_eq!(
+            get(&state, &format!("{}&key=sekrit", with_link("/media")), &[]).status(),
+            StatusCode::FOUND
+        );
+        assert_eq!(get(&state, "/health", &[]).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn basic_auth() {
+        let auth = Auth {
+            api_keys: Vec::new(),
+            basic: Some("me:pw".to_string()),
+        };
+        let state = state(auth);
+        let resp = get(&state, &with_link("/media"), &[]);
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+        let resp = get(
+            &state,
+            &with_link("/media"),
+            &[("authorization", "Basic bWU6cHc=")],
+        );
+        assert_eq!(resp.status(), StatusCode::FOUND);
+    }
+}
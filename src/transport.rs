@@ -0,0 +1,337 @@
+//! The http layer, behind a trait so requests can be recorded to a
+//! cassette file and replayed later without touching the network.
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const USER_AGENT: &str = concat!("bananagrabber/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpRequest {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl HttpRequest {
+    pub fn get<S: Into<String>>(url: S) -> HttpRequest {
+        HttpRequest {
+            url: url.into(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> HttpRequest {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn bearer_auth<T: std::fmt::Display>(self, token: T) -> HttpRequest {
+        self.header("Authorization", format!("Bearer {}", token))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// header names are lowercase
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn status(&self) -> reqwest::StatusCode {
+        reqwest::StatusCode::from_u16(self.status)
+            .unwrap_or(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|s| s.as_str())
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+
+    /// Turn 4xx and 5xx responses into errors
+    pub fn error_for_status(self) -> Result<HttpResponse> {
+        let status = self.status();
+        if status.is_client_error() || status.is_server_error() {
+            Err(anyhow::anyhow!("http status {}", status))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    /// Send a GET request, redirects are returned rather than followed
+    async fn send(&self, req: HttpRequest) -> Result<HttpResponse>;
+}
+
+/// A client builder with the settings every request from the crate shares,
+/// for fetching what doesn't go through a transport, like media files
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::ClientBuilder::new().user_agent(USER_AGENT)
+}
+
+/// Talks to the network
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        let client = client_builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("could not build http client");
+        ReqwestTransport { client }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, req: HttpRequest) -> Result<HttpResponse> {
+        let mut builder = self.client.get(&req.url);
+        for (k, v) in &req.headers {
+            builder = builder.header(k.as_str(), v.as_str());
+        }
+        let resp = builder.send().await?;
+        let status = resp.status().as_u16();
+        let headers = resp
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| Some((k.as_str().to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = resp.text().await?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: HttpRequest,
+    pub response: HttpResponse,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Cassette> {
+        let f = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let f = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(f, self)?;
+        Ok(())
+    }
+}
+
+/// Headers that carry credentials, which don't belong in a cassette
+const SECRET_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+fn redact(headers: &mut BTreeMap<String, String>) {
+    for (k, v) in headers.iter_mut() {
+        if SECRET_HEADERS.contains(&k.to_ascii_lowercase().as_str()) {
+            *v = "REDACTED".to_string();
+        }
+    }
+}
+
+/// Passes requests through to another transport, writing each
+/// request and response to the cassette as it goes.
+///
+/// Credentials are redacted, so cassettes are safe to commit.
+pub struct RecordingTransport {
+    inner: Arc<dyn HttpTransport>,
+    path: Arc<PathBuf>,
+    cassette: Arc<Mutex<Cassette>>,
+    /// Held while writing the file, so saves don't interleave
+    saving: Arc<Mutex<()>>,
+}
+
+impl RecordingTransport {
+    pub fn new<P: Into<PathBuf>>(inner: Arc<dyn HttpTransport>, path: P) -> RecordingTransport {
+        RecordingTransport {
+            inner,
+            path: Arc::new(path.into()),
+            cassette: Arc::new(Mutex::new(Cassette::default())),
+            saving: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for RecordingTransport {
+    async fn send(&self, req: HttpRequest) -> Result<HttpResponse> {
+        let response = self.inner.send(req.clone()).await?;
+        let mut interaction = Interaction {
+            request: req,
+            response: response.clone(),
+        };
+        redact(&mut interaction.request.headers);
+        redact(&mut interaction.response.headers);
+        self.cassette.lock().unwrap().interactions.push(interaction);
+
+        let (path, cassette, saving) = (
+            self.path.clone(),
+            self.cassette.clone(),
+            self.saving.clone(),
+        );
+        tokio::task::spawn_blocking(move || {
+            let _saving = saving.lock().unwrap();
+            // whoever saves last writes everything recorded so far
+            let snapshot = cassette.lock().unwrap().clone();
+            snapshot.save(path.as_ref())
+        })
+        .await??;
+        Ok(response)
+    }
+}
+
+/// Serves responses out of a cassette.
+///
+/// Requests for the same url get the recorded responses in order,
+/// repeating the last one once they run out.
+pub struct ReplayTransport {
+    interactions: Vec<Interaction>,
+    served: Mutex<Vec<bool>>,
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: Cassette) -> ReplayTransport {
+        let served = vec![false; cassette.interactions.len()];
+        ReplayTransport {
+            interactions: cassette.interactions,
+            served: Mutex::new(served),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ReplayTransport> {
+        Ok(ReplayTransport::new(Cassette::load(path)?))
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReplayTransport {
+    async fn send(&self, req: HttpRequest) -> Result<HttpResponse> {
+        let mut served = self.served.lock().unwrap();
+        let matching: Vec<usize> = self
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.request.url == req.url)
+            .map(|(idx, _)| idx)
+            .collect();
+        let idx = matching
+            .iter()
+            .find(|idx| !served[**idx])
+            .or_else(|| matching.last())
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("no recorded response for {}", req.url))?;
+        served[idx] = true;
+        Ok(self.interactions[idx].response.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(url: &str, body: &str) -> Interaction {
+        Interaction {
+            request: HttpRequest::get(url),
+            response: HttpResponse {
+                status: 200,
+                headers: BTreeMap::new(),
+                body: body.to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_serves_in_order() {
+        let transport = ReplayTransport::new(Cassette {
+            interactions: vec![
+                interaction("https://example.com/a", "first"),
+                interaction("https://example.com/b", "other"),
+                interaction("https://example.com/a", "second"),
+            ],
+        });
+        let mut bodies = Vec::new();
+        for _ in 0..3 {
+            let resp = transport
+                .send(HttpRequest::get("https://example.com/a"))
+                .await
+                .unwrap();
+            bodies.push(resp.body);
+        }
+        assert_eq!(bodies, vec!["first", "second", "second"]);
+    }
+
+    #[tokio::test]
+    async fn recording_redacts_credentials() {
+        let mut recorded = interaction("https://api.imgur.com/3/image/abc", "{}");
+        recorded
+            .response
+            .headers
+            .insert("set-cookie".to_string(), "session=1".to_string());
+        let inner = Arc::new(ReplayTransport::new(Cassette {
+            interactions: vec![recorded],
+        }));
+        let path = std::env::temp_dir().join(format!(
+            "bananagrabber-cassette-{}.json",
+            std::process::id()
+        ));
+        let transport = RecordingTransport::new(inner, &path);
+        transport
+            .send(
+                HttpRequest::get("https://api.imgur.com/3/image/abc")
+                    .header("Authorization", "Client-ID secret"),
+            )
+            .await
+            .unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!saved.contains("secret"));
+        assert!(!saved.contains("session=1"));
+        let cassette: Cassette = serde_json::from_str(&saved).unwrap();
+        assert_eq!(
+            cassette.interactions[0].request.headers["Authorization"],
+            "REDACTED"
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_unknown_url() {
+        let transport = ReplayTransport::new(Cassette::default());
+        assert!(transport
+            .send(HttpRequest::get("https://example.com/a"))
+            .await
+            .is_err());
+    }
+}